@@ -0,0 +1,1119 @@
+//! Asynchronous reader API on tokio
+//!
+//! `AsyncReader` builds its commands and parses their replies with the same code as `Reader`, but
+//! waits for replies without blocking a thread. Every operation is a future which can be
+//! cancelled by dropping it - the remaining replies to the cancelled command are discarded before
+//! the next command is sent.
+//!
+//! This needs the `async` feature.
+use async_stream::try_stream;
+use futures_core::Stream;
+use log::{debug, warn};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::time::{sleep, timeout};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::capabilities::{supported, ReaderCapabilities, MAX_ANTENNAS};
+use crate::config::{optional, ConfigChange, ReaderConfig};
+use crate::error::{Error, Result};
+use crate::iso6b::{Iso6bInventoryResult, Iso6bLockStatus, Iso6bReadResult, Iso6bWriteResult, Uid};
+use crate::operation::{self, Operation};
+use crate::protocol::{
+    AntennaHealth, BaudRate, BeeperMode, BufferedInventoryResult, Command, CommandType,
+    FastSwitchInventoryResult, GpioInput, GpioOutput, InventoryItem, InventoryResult, KillPassword,
+    KillResult, LinkProfile, LockAction, LockResult, LockTarget, MemoryBank, ReadResult,
+    ReaderIdentifier, RegionConfig, Response, ResponseCode, SelectFlag, Session, Target,
+    TriggerEvent, WriteResult, BROADCAST_ADDRESS, START_BYTE,
+};
+use crate::transport::AsyncTransport;
+use crate::{BAUD_RATE_CHANGE_TIMEOUT, CONNECT_TIMEOUT, PROBE_TIMEOUT, READ_TIMEOUT};
+
+fn timed_out() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "Timed out waiting for reader",
+    ))
+}
+
+/// An asynchronous connection to a reader
+pub struct AsyncReader<T = SerialStream> {
+    port: T,
+    antenna_count: usize,
+    address: u8,
+    baud_rate: BaudRate,
+    timeout: Duration,
+    beeper_mode: Option<BeeperMode>,
+    fast_tid: bool,
+    capabilities: Option<ReaderCapabilities>,
+    // The replies still expected to the last command, so they can be discarded if it's cancelled.
+    pending: Option<operation::Replies>,
+}
+
+impl AsyncReader {
+    /// Connect to a reader on a serial port at 115200 baud
+    ///
+    /// This must be called from within a tokio runtime. The arguments are as for `Reader::new` -
+    /// use `AsyncReader::open` to detect the number of antennas instead.
+    pub fn new(port: &str, address: u8, antenna_count: u8) -> Result<AsyncReader> {
+        AsyncReader::with_baud_rate(port, address, antenna_count, BaudRate::Baud115200)
+    }
+
+    /// Connect to a reader on a serial port at the given baud rate
+    ///
+    /// Readers default to 115200 baud, but may have been reconfigured with `set_baud_rate`.
+    pub fn with_baud_rate(
+        port: &str,
+        address: u8,
+        antenna_count: u8,
+        baud_rate: BaudRate,
+    ) -> Result<AsyncReader> {
+        let mut port = tokio_serial::new(port, 115_200)
+            .open_native_async()
+            .map_err(|e| format!("Failed to open serial port: {}", e))?;
+        port.set_baud_rate(baud_rate)?;
+        let mut reader = AsyncReader::from_transport(port, address, antenna_count)?;
+        reader.baud_rate = baud_rate;
+        Ok(reader)
+    }
+
+    /// Connect to a reader on a serial port and detect its capabilities
+    ///
+    /// This is the same as `AsyncReader::new`, but the number of antennas is detected rather
+    /// than supplied by the caller.
+    pub async fn open(port: &str, address: u8) -> Result<AsyncReader> {
+        let mut reader = AsyncReader::new(port, address, 1)?;
+        reader.probe_capabilities().await?;
+        Ok(reader)
+    }
+
+    /// Open the reader with the given identifier
+    ///
+    /// As for `Reader::open_by_identifier`, each of the serial ports in `ports` is tried in turn,
+    /// and the first reader whose identifier matches `identifier` is returned.
+    pub async fn open_by_identifier(
+        ports: &[&str],
+        address: u8,
+        antenna_count: u8,
+        identifier: &ReaderIdentifier,
+    ) -> Result<AsyncReader> {
+        for port in ports {
+            let mut reader = match AsyncReader::new(port, address, antenna_count) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    debug!("Skipping serial port {}: {}", port, e);
+                    continue;
+                }
+            };
+            reader.set_timeout(PROBE_TIMEOUT)?;
+            match reader.get_reader_identifier().await {
+                Ok(ref id) if id == identifier => {
+                    reader.set_timeout(READ_TIMEOUT)?;
+                    return Ok(reader);
+                }
+                Ok(id) => debug!("Reader on {} has identifier {}", port, id),
+                Err(e) => debug!("No reader found on {}: {}", port, e),
+            }
+        }
+        Err(Error::Program(format!(
+            "No reader found with identifier {}",
+            identifier
+        )))
+    }
+}
+
+impl AsyncReader<TcpStream> {
+    /// Connect to a reader over TCP/IP and detect its capabilities
+    ///
+    /// Unlike `Reader::connect_tcp`, the connection isn't re-established if it drops.
+    pub async fn connect_tcp<A: ToSocketAddrs>(
+        addr: A,
+        address: u8,
+    ) -> Result<AsyncReader<TcpStream>> {
+        let stream = timeout(CONNECT_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| timed_out())??;
+        stream.set_nodelay(true)?;
+        let mut reader = AsyncReader::from_transport(stream, address, 1)?;
+        reader.probe_capabilities().await?;
+        Ok(reader)
+    }
+}
+
+impl<T: AsyncTransport> AsyncReader<T> {
+    /// Create the object using an existing transport
+    ///
+    /// The arguments are as for `Reader::from_transport`.
+    pub fn from_transport(transport: T, address: u8, antenna_count: u8) -> Result<AsyncReader<T>> {
+        let mut reader = AsyncReader {
+            port: transport,
+            antenna_count: antenna_count as usize,
+            address,
+            baud_rate: BaudRate::Baud115200,
+            timeout: READ_TIMEOUT,
+            beeper_mode: None,
+            fast_tid: false,
+            capabilities: None,
+            pending: None,
+        };
+        reader.set_timeout(READ_TIMEOUT)?;
+        Ok(reader)
+    }
+
+    /// Set how long to wait for each reply from the reader
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.port.set_timeout(timeout)?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    /// Send the command for an operation to the reader
+    ///
+    /// As for `Reader`, commands which the reader is known not to support are rejected without
+    /// being sent.
+    async fn send<O: Operation>(&mut self, operation: &O) -> Result<()> {
+        if let Some(ref capabilities) = self.capabilities {
+            capabilities.check(operation.command(), operation.data())?;
+        }
+        self.discard().await?;
+        let data = Command {
+            address: self.address,
+            command: operation.command(),
+            data: operation.data().to_vec(),
+        }
+        .to_bytes();
+        debug!("Send: {:?}", data);
+        self.pending = Some(operation.replies());
+        self.port.write_all(&data).await?;
+        self.port.flush().await?;
+        Ok(())
+    }
+
+    /// Discard the remaining replies to a command which was cancelled
+    ///
+    /// This stops at the command's last reply, or once the reader has been silent for
+    /// `READ_TIMEOUT`.
+    async fn discard(&mut self) -> Result<()> {
+        while let Some(mut replies) = self.pending {
+            let last = match timeout(READ_TIMEOUT, self.read_packet()).await {
+                Err(_) => {
+                    warn!("Timed out discarding replies to {:?}", replies.command);
+                    true
+                }
+                Ok(Err(e @ Error::Io(_))) => return Err(e),
+                Ok(Err(_)) => false,
+                Ok(Ok(packet)) => {
+                    warn!("Discarded: {:?}", packet);
+                    match Response::from_bytes(&packet) {
+                        Ok(ref response)
+                            if response.command == replies.command
+                                && (self.address == BROADCAST_ADDRESS
+                                    || response.address == self.address) =>
+                        {
+                            replies.is_last(response)
+                        }
+                        // An error code from the reader is the last reply to the command.
+                        Err(Error::Protocol(_)) | Err(Error::Communication(_)) => true,
+                        _ => false,
+                    }
+                }
+            };
+            self.pending = if last { None } else { Some(replies) };
+        }
+        Ok(())
+    }
+
+    async fn read_packet(&mut self) -> Result<Vec<u8>> {
+        let mut start = [0u8; 1];
+        while start[0] != START_BYTE {
+            self.port.read_exact(&mut start).await?;
+        }
+        let mut len = [0u8; 1];
+        self.port.read_exact(&mut len).await?;
+        let len = len[0] as usize;
+        if len < 3 {
+            return Err(Error::Program(format!("Invalid packet length {}", len)));
+        }
+        let mut packet = vec![0u8; len + 2];
+        packet[0] = START_BYTE;
+        packet[1] = len as u8;
+        self.port.read_exact(&mut packet[2..]).await?;
+        Ok(packet)
+    }
+
+    async fn receive_packet(&mut self) -> Result<Response> {
+        let packet = timeout(self.timeout, self.read_packet())
+            .await
+            .map_err(|_| timed_out())??;
+        debug!("Receive: {:?}", packet);
+        Response::from_bytes(&packet)
+    }
+
+    /// Receive the next reply to the pending command, and whether it's the last one
+    ///
+    /// As for `Reader`, this drops packets from other readers and for other commands.
+    async fn receive(&mut self) -> Result<(Response, bool)> {
+        loop {
+            let packet = match self.receive_packet().await {
+                Ok(packet) => packet,
+                Err(e) => {
+                    // An error code from the reader is the last reply to the command.
+                    if let Error::Protocol(_) | Error::Communication(_) = e {
+                        self.pending = None;
+                    }
+                    return Err(e);
+                }
+            };
+            let replies = match self.pending {
+                Some(ref mut replies) => replies,
+                None => return Err(Error::Program("No command awaiting replies".to_string())),
+            };
+            if self.address != BROADCAST_ADDRESS && packet.address != self.address {
+                warn!("Dropped packet due to incorrect address: {:?}", packet);
+            } else if packet.command == replies.command {
+                let last = replies.is_last(&packet);
+                if last {
+                    self.pending = None;
+                }
+                return Ok((packet, last));
+            } else {
+                warn!("Dropped packet due to incorrect command type: {:?}", packet);
+            }
+        }
+    }
+
+    /// Run an operation, sending its command and handling each reply until the last one
+    async fn run<O: Operation>(&mut self, mut operation: O) -> Result<O::Output> {
+        self.send(&operation).await?;
+        loop {
+            let (response, last) = self.receive().await?;
+            operation.reply(response)?;
+            if last {
+                return operation.finish();
+            }
+        }
+    }
+
+    /// Forget the command if the reader didn't reply to it
+    ///
+    /// Readers don't reply to commands they don't support or which are sent to another address,
+    /// so there's nothing to discard after a timeout.
+    fn unanswered<R>(&mut self, result: Result<R>) -> Result<R> {
+        if let Err(Error::Io(_)) = result {
+            self.pending = None;
+        }
+        result
+    }
+
+    /// Whether a probe succeeded, as for `supported`
+    fn probed<R>(&mut self, result: Result<R>) -> Result<bool> {
+        supported(self.unanswered(result))
+    }
+
+    /// Detect the capabilities of the reader
+    ///
+    /// As for `Reader::probe_capabilities`, the detected number of antennas is used from then on,
+    /// and commands the reader doesn't support are rejected without being sent.
+    pub async fn probe_capabilities(&mut self) -> Result<ReaderCapabilities> {
+        self.capabilities = None;
+        let firmware_version = self.get_version().await?;
+        let antenna_count = self.probe_antenna_count().await?;
+
+        let timeout = self.timeout;
+        self.set_timeout(PROBE_TIMEOUT)?;
+        let result = self.get_fast_tid().await;
+        let fast_tid = self.probed(result);
+        let result = self.get_link_profile().await;
+        let link_profile = self.probed(result);
+        let result = self.get_reader_identifier().await;
+        let identifier = self.probed(result);
+        self.set_timeout(timeout)?;
+
+        let capabilities = ReaderCapabilities {
+            antenna_count,
+            firmware_version,
+            fast_tid: fast_tid?,
+            link_profile: link_profile?,
+            identifier: identifier?,
+        };
+        self.antenna_count = antenna_count as usize;
+        self.capabilities = Some(capabilities.clone());
+        Ok(capabilities)
+    }
+
+    /// Get the capabilities detected by `probe_capabilities`
+    pub fn capabilities(&self) -> Option<&ReaderCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    async fn probe_antenna_count(&mut self) -> Result<u8> {
+        // The reader returns the power for each antenna, unless they're all set the same.
+        let response = self
+            .run(operation::simple(CommandType::GetOutputPower))
+            .await?;
+        if response.data.len() > 1 {
+            return Ok(response.data.len() as u8);
+        }
+
+        // Otherwise find the first antenna ID the reader won't accept, then restore the working
+        // antenna.
+        let work_antenna = self.get_work_antenna().await?;
+        let mut count = 1;
+        let result = loop {
+            if count == MAX_ANTENNAS {
+                break Ok(count);
+            }
+            let accepted = match self.set_work_antenna(count).await {
+                Ok(()) => {
+                    let result = self.get_antenna_connection_detector().await;
+                    self.probed(result)
+                }
+                Err(Error::Protocol(ResponseCode::InvalidAntennaIDError)) => Ok(false),
+                Err(e) => Err(e),
+            };
+            match accepted {
+                Ok(true) => count += 1,
+                Ok(false) => break Ok(count),
+                Err(e) => break Err(e),
+            }
+        };
+        let restored = self.set_work_antenna(work_antenna).await;
+        operation::with_cleanup(result, restored)
+    }
+
+    /// Reset the reader
+    pub async fn reset(&mut self) -> Result<()> {
+        self.run(operation::reset()).await
+    }
+
+    /// Change the baud rate of the reader's serial port
+    ///
+    /// As for `Reader::set_baud_rate`, the local serial port is only returned to the previous
+    /// baud rate if the reader didn't acknowledge the change and the link check fails.
+    pub async fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<()> {
+        // Check the transport can be reconfigured before changing the reader.
+        let previous = self.baud_rate;
+        self.port.set_baud_rate(previous)?;
+
+        // Not all firmware replies to this command, so only wait briefly for a response.
+        let timeout = self.timeout;
+        self.set_timeout(BAUD_RATE_CHANGE_TIMEOUT)?;
+        let response = self.run(operation::set_baud_rate(baud_rate)).await;
+        self.set_timeout(timeout)?;
+        let acknowledged = operation::acknowledged(response)?;
+        if !acknowledged {
+            // Firmware which doesn't reply to this command never will.
+            self.pending = None;
+        }
+
+        self.port.set_baud_rate(baud_rate)?;
+        self.baud_rate = baud_rate;
+        if let Err(e) = self.get_version().await {
+            if !acknowledged {
+                self.port.set_baud_rate(previous)?;
+                self.baud_rate = previous;
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Set the address of the reader
+    pub async fn set_reader_address(&mut self, address: u8) -> Result<()> {
+        self.run(operation::set_reader_address(address)?).await?;
+        self.address = address;
+        Ok(())
+    }
+
+    /// Scan a shared RS-485 bus for readers
+    ///
+    /// As for `Reader::scan_bus`, this returns the address and firmware version of each reader
+    /// which responded, and takes around 25 seconds if there are no readers on the bus.
+    pub async fn scan_bus(&mut self) -> Result<Vec<(u8, (u8, u8))>> {
+        let address = self.address;
+        let timeout = self.timeout;
+        self.set_timeout(PROBE_TIMEOUT)?;
+
+        let mut found = Vec::new();
+        for probe in 0..BROADCAST_ADDRESS {
+            self.address = probe;
+            let result = self.get_version().await;
+            match self.unanswered(result) {
+                Ok(version) => found.push((probe, version)),
+                Err(Error::Io(_)) => (),
+                Err(e) => warn!("Error probing reader address {}: {}", probe, e),
+            }
+        }
+
+        self.address = address;
+        self.set_timeout(timeout)?;
+        Ok(found)
+    }
+
+    /// Get the firmware version of the reader
+    ///
+    /// Returns a tuple of (major, minor).
+    pub async fn get_version(&mut self) -> Result<(u8, u8)> {
+        self.run(operation::get_version()).await
+    }
+
+    /// Set the reader identifier, which is saved to flash
+    pub async fn set_reader_identifier(&mut self, identifier: &ReaderIdentifier) -> Result<()> {
+        self.run(operation::set_reader_identifier(identifier)).await
+    }
+
+    /// Get the reader identifier
+    pub async fn get_reader_identifier(&mut self) -> Result<ReaderIdentifier> {
+        self.run(operation::get_reader_identifier()).await
+    }
+
+    /// Set the Gen2 RF link profile
+    pub async fn set_link_profile(&mut self, profile: LinkProfile) -> Result<()> {
+        self.run(operation::set_link_profile(profile)).await
+    }
+
+    /// Get the Gen2 RF link profile
+    pub async fn get_link_profile(&mut self) -> Result<LinkProfile> {
+        self.run(operation::get_link_profile()).await
+    }
+
+    /// Set the working antenna ID
+    pub async fn set_work_antenna(&mut self, antenna_id: u8) -> Result<()> {
+        self.run(operation::set_work_antenna(antenna_id)).await
+    }
+
+    /// Get the working antenna ID
+    pub async fn get_work_antenna(&mut self) -> Result<u8> {
+        self.run(operation::get_work_antenna()).await
+    }
+
+    /// Get the antenna connection detector threshold in dB, or 0 if disabled
+    pub async fn get_antenna_connection_detector(&mut self) -> Result<i8> {
+        self.run(operation::get_antenna_connection_detector()).await
+    }
+
+    /// Set the antenna connection detector threshold in dB, or 0 to disable it
+    pub async fn set_antenna_connection_detector(&mut self, threshold: i8) -> Result<()> {
+        self.run(operation::set_antenna_connection_detector(threshold))
+            .await
+    }
+
+    /// Check which antenna ports have a working antenna connected
+    ///
+    /// The arguments and result are as for `Reader::antenna_health`. The working antenna is
+    /// restored afterwards, even if the check fails.
+    pub async fn antenna_health(
+        &mut self,
+        frequency: f32,
+        poor_match: i8,
+    ) -> Result<Vec<AntennaHealth>> {
+        let work_antenna = self.get_work_antenna().await?;
+        let result = self.check_antennas(frequency, poor_match).await;
+        let restored = self.set_work_antenna(work_antenna).await;
+        operation::with_cleanup(result, restored)
+    }
+
+    async fn check_antennas(
+        &mut self,
+        frequency: f32,
+        poor_match: i8,
+    ) -> Result<Vec<AntennaHealth>> {
+        let mut health = Vec::new();
+        for antenna in 0..self.antenna_count as u8 {
+            self.set_work_antenna(antenna).await?;
+            let detector_threshold = self.get_antenna_connection_detector().await?;
+            let return_loss = self.measure_return_loss(frequency).await?;
+            let inventory = self.real_time_inventory(1).await;
+            health.push(AntennaHealth {
+                antenna,
+                status: operation::antenna_status(
+                    inventory,
+                    return_loss,
+                    detector_threshold,
+                    poor_match,
+                )?,
+                return_loss,
+                detector_threshold,
+            });
+        }
+        Ok(health)
+    }
+
+    /// Set the output power per antenna in dBm and save to flash
+    pub async fn set_output_power(&mut self, power: &[u8]) -> Result<()> {
+        self.run(operation::set_output_power(power, self.antenna_count)?)
+            .await
+    }
+
+    /// Set the output power for all antennas in dBm without saving it to flash
+    pub async fn set_temporary_output_power(&mut self, power: u8) -> Result<()> {
+        self.run(operation::set_temporary_output_power(power)).await
+    }
+
+    /// Run `f` with a temporary output power, restoring the previous power afterwards
+    ///
+    /// As for `Reader::with_temporary_output_power`, the previous power is restored even if `f`
+    /// fails, and antennas with different output powers are rejected. `f` returns a boxed
+    /// future, as for `with_epc_match`.
+    pub async fn with_temporary_output_power<R, F>(&mut self, power: u8, f: F) -> Result<R>
+    where
+        F: for<'a> FnOnce(
+            &'a mut AsyncReader<T>,
+        ) -> Pin<Box<dyn Future<Output = Result<R>> + Send + 'a>>,
+    {
+        let previous = operation::restorable_output_power(&self.get_output_power().await?)?;
+        self.set_temporary_output_power(power).await?;
+        let result = f(self).await;
+        let restored = self.set_temporary_output_power(previous).await;
+        operation::with_cleanup(result, restored)
+    }
+
+    /// Get the output power per antenna in dBm
+    pub async fn get_output_power(&mut self) -> Result<Vec<u8>> {
+        self.run(operation::get_output_power(self.antenna_count))
+            .await
+    }
+
+    /// Get the frequency region configuration
+    pub async fn get_frequency_region(&mut self) -> Result<RegionConfig> {
+        self.run(operation::get_frequency_region()).await
+    }
+
+    /// Set the frequency region configuration, checking it first
+    pub async fn set_frequency_region(&mut self, config: RegionConfig) -> Result<()> {
+        self.run(operation::set_frequency_region(config)?).await
+    }
+
+    /// Read the level of a GPIO input pin
+    pub async fn read_gpio(&mut self, input: GpioInput) -> Result<bool> {
+        self.run(operation::read_gpio(input)).await
+    }
+
+    /// Set the level of a GPIO output pin
+    pub async fn write_gpio(&mut self, output: GpioOutput, value: bool) -> Result<()> {
+        self.run(operation::write_gpio(output, value)).await
+    }
+
+    /// Run real-time inventory whenever a GPIO input is active
+    ///
+    /// The arguments are as for `Reader::triggered_inventory`. This runs until `handler` returns
+    /// false.
+    pub async fn triggered_inventory<F>(
+        &mut self,
+        input: GpioInput,
+        active: bool,
+        poll_interval: Duration,
+        repeat: u8,
+        mut handler: F,
+    ) -> Result<()>
+    where
+        F: FnMut(TriggerEvent) -> bool,
+    {
+        let mut triggered = false;
+        loop {
+            let level = self.read_gpio(input).await? == active;
+            if let Some(event) = operation::trigger_change(&mut triggered, level) {
+                if !handler(event) {
+                    return Ok(());
+                }
+            }
+            if triggered {
+                let result = self.real_time_inventory(repeat).await?;
+                if !handler(TriggerEvent::Inventory(result)) {
+                    return Ok(());
+                }
+            } else {
+                sleep(poll_interval).await;
+            }
+        }
+    }
+
+    /// Set when the reader's beeper sounds
+    pub async fn set_beeper_mode(&mut self, mode: BeeperMode) -> Result<()> {
+        self.run(operation::set_beeper_mode(mode)).await?;
+        self.beeper_mode = Some(mode);
+        Ok(())
+    }
+
+    /// Get the beeper mode last set with `set_beeper_mode`
+    pub fn beeper_mode(&self) -> Option<BeeperMode> {
+        self.beeper_mode
+    }
+
+    /// Fetch the temperature of the reader in celsius
+    pub async fn get_temperature(&mut self) -> Result<i8> {
+        self.run(operation::get_temperature()).await
+    }
+
+    /// Measure the return loss in dB of the selected antenna
+    pub async fn measure_return_loss(&mut self, frequency: f32) -> Result<i8> {
+        self.run(operation::measure_return_loss(frequency)?).await
+    }
+
+    /// Enable or disable Impinj FastTID, optionally saving the setting to flash
+    pub async fn set_fast_tid(&mut self, enabled: bool, save: bool) -> Result<()> {
+        self.run(operation::set_fast_tid(enabled, save)).await?;
+        self.fast_tid = enabled;
+        Ok(())
+    }
+
+    /// Get whether Impinj FastTID is enabled
+    pub async fn get_fast_tid(&mut self) -> Result<bool> {
+        self.fast_tid = self.run(operation::get_fast_tid()).await?;
+        Ok(self.fast_tid)
+    }
+
+    /// Read all of the reader's settings
+    ///
+    /// As for `Reader::read_config`, settings the reader doesn't support are left unset.
+    pub async fn read_config(&mut self) -> Result<ReaderConfig> {
+        let firmware_version = self.get_version().await?;
+        let region = self.get_frequency_region().await?;
+        let output_power = self.get_output_power().await?;
+        let work_antenna = self.get_work_antenna().await?;
+        let antenna_detector = self.get_antenna_connection_detector().await?;
+
+        let timeout = self.timeout;
+        self.set_timeout(PROBE_TIMEOUT)?;
+        let result = self.get_link_profile().await;
+        let link_profile = optional(self.unanswered(result));
+        let result = self.get_reader_identifier().await;
+        let identifier = optional(self.unanswered(result));
+        let result = self.get_fast_tid().await;
+        let fast_tid = optional(self.unanswered(result));
+        self.set_timeout(timeout)?;
+
+        Ok(ReaderConfig {
+            firmware_version,
+            region,
+            output_power,
+            work_antenna,
+            antenna_detector,
+            beeper_mode: self.beeper_mode(),
+            link_profile: link_profile?,
+            identifier: identifier?,
+            fast_tid: fast_tid?,
+            address: self.address,
+        })
+    }
+
+    /// Apply a configuration to the reader
+    ///
+    /// As for `Reader::apply_config`, only settings which differ are written, and the reader
+    /// address is changed last. Returns the list of changes which were applied.
+    pub async fn apply_config(&mut self, config: &ReaderConfig) -> Result<Vec<ConfigChange>> {
+        let changes = self.read_config().await?.diff(config);
+        for change in &changes {
+            match change {
+                ConfigChange::Region(region) => self.set_frequency_region(*region).await?,
+                ConfigChange::OutputPower(power) => self.set_output_power(power).await?,
+                ConfigChange::WorkAntenna(antenna) => self.set_work_antenna(*antenna).await?,
+                ConfigChange::AntennaDetector(threshold) => {
+                    self.set_antenna_connection_detector(*threshold).await?
+                }
+                ConfigChange::BeeperMode(mode) => self.set_beeper_mode(*mode).await?,
+                ConfigChange::LinkProfile(profile) => self.set_link_profile(*profile).await?,
+                ConfigChange::Identifier(identifier) => {
+                    self.set_reader_identifier(identifier).await?
+                }
+                ConfigChange::FastTID(enabled) => self.set_fast_tid(*enabled, true).await?,
+                ConfigChange::Address(address) => self.set_reader_address(*address).await?,
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Start an inventory operation on the selected antenna and return the result once it's
+    /// complete
+    pub async fn real_time_inventory(&mut self, repeat: u8) -> Result<InventoryResult> {
+        self.run(operation::real_time_inventory(repeat, self.fast_tid))
+            .await
+    }
+
+    /// Start an inventory operation on the selected antenna, returning tags as they're read
+    ///
+    /// The stream ends once the inventory is complete. It can be dropped to stop waiting for
+    /// tags early.
+    pub fn real_time_inventory_stream(
+        &mut self,
+        repeat: u8,
+    ) -> impl Stream<Item = Result<InventoryItem>> + '_ {
+        try_stream! {
+            let mut inventory = operation::real_time_inventory(repeat, self.fast_tid);
+            self.send(&inventory).await?;
+            loop {
+                let (response, last) = self.receive().await?;
+                inventory.reply(response)?;
+                for item in inventory.take_items() {
+                    yield item;
+                }
+                if last {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Start an inventory operation with the given Gen2 session and target
+    ///
+    /// The arguments are as for `Reader::session_target_inventory`.
+    pub async fn session_target_inventory(
+        &mut self,
+        session: Session,
+        target: Target,
+        select: Option<SelectFlag>,
+        phase: bool,
+        repeat: u8,
+    ) -> Result<InventoryResult> {
+        self.run(operation::session_target_inventory(
+            session,
+            target,
+            select,
+            phase,
+            repeat,
+            self.fast_tid,
+        ))
+        .await
+    }
+
+    /// Run an inventory which switches between antennas on the reader itself
+    ///
+    /// The arguments are as for `Reader::fast_switch_inventory`.
+    pub async fn fast_switch_inventory(
+        &mut self,
+        sequence: &[(u8, u8)],
+        interval: u8,
+        repeat: u8,
+    ) -> Result<FastSwitchInventoryResult> {
+        self.run(operation::fast_switch_inventory(
+            sequence,
+            self.antenna_count,
+            interval,
+            repeat,
+            self.fast_tid,
+        )?)
+        .await
+    }
+
+    /// Start an inventory operation on the selected antenna, storing the results in the reader's
+    /// buffer
+    ///
+    /// If no tags are found, the reader doesn't report which antenna was used, so this returns
+    /// `Error::Communication(ResponseCode::NoTagError)` rather than an empty result.
+    pub async fn buffered_inventory(&mut self, repeat: u8) -> Result<BufferedInventoryResult> {
+        self.run(operation::buffered_inventory(repeat)).await
+    }
+
+    /// Get the number of unique tags in the inventory buffer
+    pub async fn get_buffer_tag_count(&mut self) -> Result<u16> {
+        self.run(operation::get_buffer_tag_count()).await
+    }
+
+    /// Fetch the tags in the inventory buffer, leaving the buffer intact
+    pub async fn get_inventory_buffer(&mut self) -> Result<Vec<InventoryItem>> {
+        self.run(operation::inventory_buffer(
+            CommandType::GetInventoryBuffer,
+            self.fast_tid,
+        ))
+        .await
+    }
+
+    /// Fetch the tags in the inventory buffer and clear the buffer
+    pub async fn get_and_reset_inventory_buffer(&mut self) -> Result<Vec<InventoryItem>> {
+        self.run(operation::inventory_buffer(
+            CommandType::GetAndResetInventoryBuffer,
+            self.fast_tid,
+        ))
+        .await
+    }
+
+    /// Clear the inventory buffer
+    pub async fn reset_inventory_buffer(&mut self) -> Result<()> {
+        self.run(operation::reset_inventory_buffer()).await
+    }
+
+    /// Read data from tags
+    ///
+    /// The arguments are as for `Reader::read`.
+    pub async fn read(
+        &mut self,
+        bank: MemoryBank,
+        password: &[u8],
+        start: u8,
+        length: u8,
+    ) -> Result<Vec<ReadResult>> {
+        self.run(operation::read(bank, password, start, length))
+            .await
+    }
+
+    /// Write data to tags
+    ///
+    /// The arguments are as for `Reader::write`.
+    pub async fn write(
+        &mut self,
+        bank: MemoryBank,
+        password: &[u8],
+        start_word: u8,
+        data: &[u8],
+    ) -> Result<Vec<WriteResult>> {
+        self.run(operation::write(bank, password, start_word, data)?)
+            .await
+    }
+
+    /// Lock or unlock a memory region on tags
+    ///
+    /// The arguments are as for `Reader::lock`.
+    pub async fn lock(
+        &mut self,
+        target: LockTarget,
+        action: LockAction,
+        password: &[u8],
+    ) -> Result<Vec<LockResult>> {
+        self.run(operation::lock(target, action, password)).await
+    }
+
+    /// Permanently kill a tag
+    ///
+    /// As with `Reader::kill`, this refuses to run unless an EPC access match is set - use
+    /// `with_epc_match` to target a single tag.
+    pub async fn kill(&mut self, password: KillPassword) -> Result<Vec<KillResult>> {
+        let epc_match = self.get_epc_match().await?;
+        self.run(operation::kill(password, epc_match)?).await
+    }
+
+    /// Set the EPC access match, or clear it if `epc` is empty
+    pub async fn set_epc_match(&mut self, epc: &[u8]) -> Result<()> {
+        self.run(operation::set_epc_match(epc)).await
+    }
+
+    /// Get the EPC access match, or `None` if no match is set
+    pub async fn get_epc_match(&mut self) -> Result<Option<Vec<u8>>> {
+        self.run(operation::get_epc_match()).await
+    }
+
+    /// Run `f` with the EPC access match set to `epc`
+    ///
+    /// As for `Reader::with_epc_match`, the match is cleared once `f` completes, even if it
+    /// fails. `f` returns a boxed future, for example
+    /// `|reader| Box::pin(reader.kill(password))`. If the future returned by this is dropped
+    /// before it completes, the match is left set.
+    pub async fn with_epc_match<R, F>(&mut self, epc: &[u8], f: F) -> Result<R>
+    where
+        F: for<'a> FnOnce(
+            &'a mut AsyncReader<T>,
+        ) -> Pin<Box<dyn Future<Output = Result<R>> + Send + 'a>>,
+    {
+        self.run(operation::set_scoped_epc_match(epc)?).await?;
+        let result = f(self).await;
+        let cleared = self.set_epc_match(&[]).await;
+        operation::with_cleanup(result, cleared)
+    }
+
+    /// Inventory ISO 18000-6B tags on the selected antenna
+    pub async fn inventory_6b(&mut self) -> Result<Iso6bInventoryResult> {
+        self.run(operation::inventory_6b()).await
+    }
+
+    /// Read `length` bytes from a 6B tag, starting at byte `start`
+    pub async fn read_6b(&mut self, uid: &Uid, start: u8, length: u8) -> Result<Iso6bReadResult> {
+        self.run(operation::read_6b(uid, start, length)).await
+    }
+
+    /// Write bytes to a 6B tag, starting at byte `start`
+    ///
+    /// Check `written` in the result, as the write may stop early if it reaches a locked byte.
+    pub async fn write_6b(
+        &mut self,
+        uid: &Uid,
+        start: u8,
+        data: &[u8],
+    ) -> Result<Iso6bWriteResult> {
+        self.run(operation::write_6b(uid, start, data)?).await
+    }
+
+    /// Permanently lock the byte at `address` on a 6B tag
+    pub async fn lock_6b(&mut self, uid: &Uid, address: u8) -> Result<Iso6bLockStatus> {
+        self.run(operation::lock_6b(uid, address)).await
+    }
+
+    /// Query whether the byte at `address` on a 6B tag is locked
+    pub async fn query_lock_6b(&mut self, uid: &Uid, address: u8) -> Result<bool> {
+        self.run(operation::query_lock_6b(uid, address)).await
+    }
+}
+
+#[tokio::test]
+async fn test_async_reader() {
+    use crate::emulator::{spawn_tcp, EmulatedTag, Emulator};
+    use std::sync::{Arc, Mutex};
+
+    let mut emulator = Emulator::new(2);
+    for i in 0..3 {
+        let mut tag = EmulatedTag::new(&[0x30, 0, 0, 0, 0, 0, 0, i]);
+        tag.kill_password = [0, 0, 0, 1];
+        emulator.tags.push(tag);
+    }
+    let addr = spawn_tcp(Arc::new(Mutex::new(emulator))).unwrap();
+    let mut reader = AsyncReader::connect_tcp(addr, 1).await.unwrap();
+    assert_eq!(reader.capabilities().unwrap().antenna_count, 2);
+    assert_eq!(reader.get_version().await.unwrap(), (1, 7));
+
+    let mut epcs = Vec::new();
+    {
+        let mut stream = Box::pin(reader.real_time_inventory_stream(1));
+        while let Some(item) = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            epcs.push(item.unwrap().epc);
+        }
+    }
+    assert_eq!(epcs.len(), 3);
+
+    let results = reader
+        .write(MemoryBank::User, &[0; 4], 0, &[0xAB, 0xCD])
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 3);
+    let results = reader.read(MemoryBank::User, &[0; 4], 0, 1).await.unwrap();
+    assert_eq!(results[0].data, vec![0xAB, 0xCD]);
+
+    let password = KillPassword::new(1).unwrap();
+    assert!(reader.kill(password).await.is_err());
+    let results = reader
+        .with_epc_match(&epcs[1], |reader| Box::pin(reader.kill(password)))
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].status, ResponseCode::Success);
+    assert_eq!(reader.get_epc_match().await.unwrap(), None);
+    assert_eq!(reader.real_time_inventory(1).await.unwrap().items.len(), 2);
+}
+
+#[tokio::test]
+async fn test_async_reader_cancel() {
+    use crate::emulator::{spawn_tcp, EmulatedTag, Emulator};
+    use std::sync::{Arc, Mutex};
+
+    let mut emulator = Emulator::new(1);
+    for i in 0..3 {
+        emulator
+            .tags
+            .push(EmulatedTag::new(&[0x30, 0, 0, 0, 0, 0, 0, i]));
+    }
+    let emulator = Arc::new(Mutex::new(emulator));
+    let addr = spawn_tcp(emulator.clone()).unwrap();
+    let mut reader = AsyncReader::connect_tcp(addr, 1).await.unwrap();
+
+    // Stop after the first tag, with the reader pausing for longer than `PROBE_TIMEOUT` between
+    // the rest of the replies, which must all be discarded.
+    emulator.lock().unwrap().reply_interval = Duration::from_millis(200);
+    {
+        let mut stream = Box::pin(reader.real_time_inventory_stream(1));
+        let item = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await;
+        assert!(item.unwrap().is_ok());
+    }
+    let result = reader.real_time_inventory(1).await.unwrap();
+    assert_eq!(result.items.len(), 3);
+    assert_eq!(result.total_read, 3);
+    assert_eq!(reader.get_version().await.unwrap(), (1, 7));
+}
+
+#[tokio::test]
+async fn test_async_reader_antennas() {
+    use crate::emulator::{spawn_tcp, EmulatedTag, Emulator};
+    use crate::protocol::AntennaStatus;
+    use std::sync::{Arc, Mutex};
+
+    let mut emulator = Emulator::new(2);
+    emulator.return_loss = vec![20, 3];
+    emulator
+        .tags
+        .push(EmulatedTag::new(&[0x30, 0, 0, 0, 0, 0, 0, 1]));
+    let emulator = Arc::new(Mutex::new(emulator));
+    let addr = spawn_tcp(emulator.clone()).unwrap();
+    let mut reader = AsyncReader::connect_tcp(addr, 1).await.unwrap();
+
+    reader.set_antenna_connection_detector(10).await.unwrap();
+    reader.set_work_antenna(1).await.unwrap();
+    let health = reader.antenna_health(865.0, 10).await.unwrap();
+    let status: Vec<_> = health.iter().map(|antenna| antenna.status).collect();
+    assert_eq!(
+        status,
+        vec![AntennaStatus::Connected, AntennaStatus::Disconnected]
+    );
+    assert_eq!(reader.get_work_antenna().await.unwrap(), 1);
+    reader.set_work_antenna(0).await.unwrap();
+
+    let power = reader
+        .with_temporary_output_power(20, |reader| Box::pin(reader.get_output_power()))
+        .await
+        .unwrap();
+    assert_eq!(power, vec![20, 20]);
+    assert_eq!(reader.get_output_power().await.unwrap(), vec![30, 30]);
+
+    // The trigger is released after the first inventory run.
+    emulator.lock().unwrap().gpio_inputs[0] = true;
+    let mut events = Vec::new();
+    reader
+        .triggered_inventory(
+            GpioInput::Gpio1,
+            true,
+            Duration::from_millis(10),
+            1,
+            |event| {
+                let stop = event == TriggerEvent::Stop;
+                if let TriggerEvent::Inventory(_) = event {
+                    emulator.lock().unwrap().gpio_inputs[0] = false;
+                }
+                events.push(event);
+                !stop
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0], TriggerEvent::Start);
+    match events[1] {
+        TriggerEvent::Inventory(ref result) => assert_eq!(result.items.len(), 1),
+        ref other => panic!("Unexpected event: {:?}", other),
+    }
+    assert_eq!(events[2], TriggerEvent::Stop);
+}
+
+#[tokio::test]
+async fn test_async_reader_config() {
+    use crate::emulator::{spawn_tcp, Emulator};
+    use std::sync::{Arc, Mutex};
+
+    let emulator = Arc::new(Mutex::new(Emulator::new(2)));
+    let addr = spawn_tcp(emulator.clone()).unwrap();
+    // Without probing the capabilities first, the commands the emulator ignores time out.
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut reader = AsyncReader::from_transport(stream, 1, 2).unwrap();
+
+    let config = reader.read_config().await.unwrap();
+    assert_eq!(config.output_power, vec![30, 30]);
+    assert_eq!(config.link_profile, None);
+    assert_eq!(config.identifier, None);
+    assert_eq!(config.fast_tid, None);
+    assert_eq!(reader.timeout, READ_TIMEOUT);
+
+    let target = ReaderConfig {
+        output_power: vec![20, 25],
+        beeper_mode: Some(BeeperMode::Quiet),
+        address: 2,
+        ..config
+    };
+    assert_eq!(
+        reader.apply_config(&target).await.unwrap(),
+        vec![
+            ConfigChange::OutputPower(vec![20, 25]),
+            ConfigChange::BeeperMode(BeeperMode::Quiet),
+            ConfigChange::Address(2),
+        ]
+    );
+    assert_eq!(emulator.lock().unwrap().address, 2);
+    assert_eq!(reader.read_config().await.unwrap(), target);
+}
@@ -0,0 +1,176 @@
+//! Reader capability detection
+//!
+//! The protocol has no command which reports what a reader supports, so this works it out by
+//! trying commands and seeing which ones the reader accepts.
+use crate::error::{Error, Result};
+use crate::operation;
+use crate::protocol::{CommandType, ResponseCode};
+use crate::transport::Transport;
+use crate::{Reader, PROBE_TIMEOUT, READ_TIMEOUT};
+
+// Antenna IDs are two bits wide in the protocol, so readers can have at most four ports.
+pub(crate) const MAX_ANTENNAS: u8 = 4;
+
+/// Features supported by a reader
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReaderCapabilities {
+    /// Number of antenna ports
+    pub antenna_count: u8,
+    /// Firmware version (major, minor)
+    pub firmware_version: (u8, u8),
+    /// Whether Impinj FastTID can be configured
+    pub fast_tid: bool,
+    /// Whether the RF link profile can be configured
+    pub link_profile: bool,
+    /// Whether the reader has an identifier
+    pub identifier: bool,
+}
+
+impl ReaderCapabilities {
+    /// Whether the reader supports a command
+    pub(crate) fn supports(&self, command: CommandType) -> bool {
+        match command {
+            CommandType::SetImpinjFastTID
+            | CommandType::SetAndSaveImpinjFastTIC
+            | CommandType::GetImpinjFastTID => self.fast_tid,
+            CommandType::SetRFLinkProfile | CommandType::GetRFLinkProfile => self.link_profile,
+            CommandType::SetReaderIdentifier | CommandType::GetReaderIdentifier => self.identifier,
+            _ => true,
+        }
+    }
+
+    /// Reject commands which the reader is known not to support, and antenna IDs which it is
+    /// known not to have
+    pub(crate) fn check(&self, command: CommandType, data: &[u8]) -> Result<()> {
+        if !self.supports(command) {
+            return Err(Error::Unsupported(format!("{:?}", command)));
+        }
+        match (command, data.first()) {
+            (CommandType::SetWorkAntenna, Some(antenna_id))
+                if *antenna_id >= self.antenna_count =>
+            {
+                Err(Error::Protocol(ResponseCode::InvalidAntennaIDError))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether a probe succeeded, treating errors returned by the reader and timeouts as the
+/// command being unsupported.
+pub(crate) fn supported<T>(result: Result<T>) -> Result<bool> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(Error::Protocol(_)) | Err(Error::Io(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+impl Reader {
+    /// Connect to a reader on a serial port and detect its capabilities
+    ///
+    /// This is the same as `Reader::new`, but the number of antennas is detected rather than
+    /// supplied by the caller.
+    pub fn open(port: &str, address: u8) -> Result<Reader> {
+        let mut reader = Reader::new(port, address, 1)?;
+        reader.probe_capabilities()?;
+        Ok(reader)
+    }
+}
+
+impl<T: Transport> Reader<T> {
+    /// Detect the capabilities of the reader
+    ///
+    /// Once this has been called, the detected number of antennas is used in place of the one
+    /// passed to `Reader::new`, and commands the reader doesn't support are rejected without
+    /// being sent.
+    pub fn probe_capabilities(&mut self) -> Result<ReaderCapabilities> {
+        self.capabilities = None;
+        let firmware_version = self.get_version()?;
+        let antenna_count = self.probe_antenna_count()?;
+
+        self.set_timeout(PROBE_TIMEOUT)?;
+        let fast_tid = supported(self.get_fast_tid());
+        let link_profile = supported(self.get_link_profile());
+        let identifier = supported(self.get_reader_identifier());
+        self.set_timeout(READ_TIMEOUT)?;
+
+        let capabilities = ReaderCapabilities {
+            antenna_count,
+            firmware_version,
+            fast_tid: fast_tid?,
+            link_profile: link_profile?,
+            identifier: identifier?,
+        };
+        self.antenna_count = antenna_count as usize;
+        self.capabilities = Some(capabilities.clone());
+        Ok(capabilities)
+    }
+
+    /// Get the capabilities detected by `probe_capabilities`
+    pub fn capabilities(&self) -> Option<&ReaderCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    fn probe_antenna_count(&mut self) -> Result<u8> {
+        // The reader returns the power for each antenna, unless they're all set the same.
+        let response = self.run(operation::simple(CommandType::GetOutputPower))?;
+        if response.data.len() > 1 {
+            return Ok(response.data.len() as u8);
+        }
+
+        // Otherwise find the first antenna ID the reader won't accept.
+        self.restoring_work_antenna(|reader| {
+            let mut count = 1;
+            while count < MAX_ANTENNAS {
+                let accepted = match reader.set_work_antenna(count) {
+                    Ok(()) => supported(reader.get_antenna_connection_detector())?,
+                    Err(Error::Protocol(ResponseCode::InvalidAntennaIDError)) => false,
+                    Err(e) => return Err(e),
+                };
+                if !accepted {
+                    break;
+                }
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+}
+
+#[test]
+fn test_supports() {
+    let capabilities = ReaderCapabilities {
+        antenna_count: 4,
+        firmware_version: (1, 7),
+        fast_tid: false,
+        link_profile: true,
+        identifier: true,
+    };
+    assert!(!capabilities.supports(CommandType::SetImpinjFastTID));
+    assert!(capabilities.supports(CommandType::GetRFLinkProfile));
+    assert!(capabilities.supports(CommandType::RealTimeInventory));
+    assert!(capabilities
+        .check(CommandType::SetWorkAntenna, &[3])
+        .is_ok());
+    assert!(capabilities
+        .check(CommandType::SetWorkAntenna, &[4])
+        .is_err());
+}
+
+#[test]
+fn test_probe_antenna_count_restores_work_antenna() {
+    use crate::mock::{frame, MockTransport};
+
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x77, &[]))
+        .respond(frame(1, 0x77, &[30]))
+        .expect(frame(1, 0x75, &[]))
+        .respond(frame(1, 0x75, &[0]))
+        .expect(frame(1, 0x74, &[1]))
+        .timeout()
+        .expect(frame(1, 0x74, &[0]))
+        .respond(frame(1, 0x74, &[0x10]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    assert!(reader.probe_antenna_count().is_err());
+}
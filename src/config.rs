@@ -0,0 +1,282 @@
+//! Reader configuration snapshots
+//!
+//! A `ReaderConfig` captures all of the readable settings of a reader, so they can be saved (with
+//! the `serde` feature) and applied to a replacement reader.
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::protocol::{BeeperMode, LinkProfile, ReaderIdentifier, RegionConfig};
+use crate::transport::Transport;
+use crate::{Reader, PROBE_TIMEOUT, READ_TIMEOUT};
+
+/// A snapshot of a reader's settings
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReaderConfig {
+    /// Firmware version (major, minor). This is informational and is never applied.
+    pub firmware_version: (u8, u8),
+    pub region: RegionConfig,
+    /// Output power per antenna in dBm
+    pub output_power: Vec<u8>,
+    pub work_antenna: u8,
+    /// Antenna connection detector threshold in dB, or 0 if disabled
+    pub antenna_detector: i8,
+    /// Beeper mode, if known - the reader has no command to read this back
+    pub beeper_mode: Option<BeeperMode>,
+    /// Link profile, if supported by the reader
+    pub link_profile: Option<LinkProfile>,
+    /// Reader identifier, if supported by the reader
+    pub identifier: Option<ReaderIdentifier>,
+    /// Whether Impinj FastTID is enabled, if supported by the reader
+    pub fast_tid: Option<bool>,
+    pub address: u8,
+}
+
+/// A single setting which differs between two configurations
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigChange {
+    Region(RegionConfig),
+    OutputPower(Vec<u8>),
+    WorkAntenna(u8),
+    AntennaDetector(i8),
+    BeeperMode(BeeperMode),
+    LinkProfile(LinkProfile),
+    Identifier(ReaderIdentifier),
+    FastTID(bool),
+    Address(u8),
+}
+
+impl ReaderConfig {
+    /// List the changes needed to turn this configuration into `target`
+    ///
+    /// Settings which are `None` in `target` are left unchanged. The reader address is always
+    /// the last change, as the reader must be addressed differently afterwards.
+    pub fn diff(&self, target: &ReaderConfig) -> Vec<ConfigChange> {
+        let mut changes = Vec::new();
+        if self.region != target.region {
+            changes.push(ConfigChange::Region(target.region));
+        }
+        if self.output_power != target.output_power {
+            changes.push(ConfigChange::OutputPower(target.output_power.clone()));
+        }
+        if self.work_antenna != target.work_antenna {
+            changes.push(ConfigChange::WorkAntenna(target.work_antenna));
+        }
+        if self.antenna_detector != target.antenna_detector {
+            changes.push(ConfigChange::AntennaDetector(target.antenna_detector));
+        }
+        if let Some(mode) = target.beeper_mode {
+            if self.beeper_mode != Some(mode) {
+                changes.push(ConfigChange::BeeperMode(mode));
+            }
+        }
+        if let Some(profile) = target.link_profile {
+            if self.link_profile != Some(profile) {
+                changes.push(ConfigChange::LinkProfile(profile));
+            }
+        }
+        if let Some(identifier) = target.identifier {
+            if self.identifier != Some(identifier) {
+                changes.push(ConfigChange::Identifier(identifier));
+            }
+        }
+        if let Some(fast_tid) = target.fast_tid {
+            if self.fast_tid != Some(fast_tid) {
+                changes.push(ConfigChange::FastTID(fast_tid));
+            }
+        }
+        if self.address != target.address {
+            changes.push(ConfigChange::Address(target.address));
+        }
+        changes
+    }
+}
+
+/// Treat an error returned by the reader, or no reply at all, as the setting being unsupported
+pub(crate) fn optional<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::Protocol(_)) | Err(Error::Io(_)) | Err(Error::Unsupported(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl<T: Transport> Reader<T> {
+    /// Read all of the reader's settings
+    pub fn read_config(&mut self) -> Result<ReaderConfig> {
+        let firmware_version = self.get_version()?;
+        let region = self.get_frequency_region()?;
+        let output_power = self.get_output_power()?;
+        let work_antenna = self.get_work_antenna()?;
+        let antenna_detector = self.get_antenna_connection_detector()?;
+
+        // Some readers don't reply at all to commands they don't support, so don't wait long.
+        self.set_timeout(PROBE_TIMEOUT)?;
+        let link_profile = optional(self.get_link_profile());
+        let identifier = optional(self.get_reader_identifier());
+        let fast_tid = optional(self.get_fast_tid());
+        self.set_timeout(READ_TIMEOUT)?;
+
+        Ok(ReaderConfig {
+            firmware_version,
+            region,
+            output_power,
+            work_antenna,
+            antenna_detector,
+            beeper_mode: self.beeper_mode(),
+            link_profile: link_profile?,
+            identifier: identifier?,
+            fast_tid: fast_tid?,
+            address: self.address,
+        })
+    }
+
+    /// Apply a configuration to the reader
+    ///
+    /// Only settings which differ from the reader's current settings are written. Returns the
+    /// list of changes which were applied.
+    pub fn apply_config(&mut self, config: &ReaderConfig) -> Result<Vec<ConfigChange>> {
+        let changes = self.read_config()?.diff(config);
+        for change in &changes {
+            match change {
+                ConfigChange::Region(region) => self.set_frequency_region(*region)?,
+                ConfigChange::OutputPower(power) => self.set_output_power(power)?,
+                ConfigChange::WorkAntenna(antenna) => self.set_work_antenna(*antenna)?,
+                ConfigChange::AntennaDetector(threshold) => {
+                    self.set_antenna_connection_detector(*threshold)?
+                }
+                ConfigChange::BeeperMode(mode) => self.set_beeper_mode(*mode)?,
+                ConfigChange::LinkProfile(profile) => self.set_link_profile(*profile)?,
+                ConfigChange::Identifier(identifier) => self.set_reader_identifier(identifier)?,
+                ConfigChange::FastTID(enabled) => self.set_fast_tid(*enabled, true)?,
+                ConfigChange::Address(address) => self.set_reader_address(*address)?,
+            }
+        }
+        Ok(changes)
+    }
+}
+
+#[test]
+fn test_config_diff() {
+    use crate::protocol::FrequencyRegion;
+
+    let current = ReaderConfig {
+        firmware_version: (1, 7),
+        region: RegionConfig::Standard {
+            region: FrequencyRegion::ETSI,
+            start_channel: 0,
+            end_channel: 6,
+        },
+        output_power: vec![30, 30, 30, 30],
+        work_antenna: 0,
+        antenna_detector: -6,
+        beeper_mode: None,
+        link_profile: Some(LinkProfile::Profile1),
+        identifier: None,
+        fast_tid: Some(false),
+        address: 1,
+    };
+    let mut target = current.clone();
+    target.firmware_version = (1, 8);
+    assert_eq!(current.diff(&target), vec![]);
+
+    target.address = 2;
+    target.output_power = vec![20, 20, 20, 20];
+    target.beeper_mode = Some(BeeperMode::Quiet);
+    target.fast_tid = None;
+    assert_eq!(
+        current.diff(&target),
+        vec![
+            ConfigChange::OutputPower(vec![20, 20, 20, 20]),
+            ConfigChange::BeeperMode(BeeperMode::Quiet),
+            ConfigChange::Address(2),
+        ]
+    );
+}
+
+#[cfg(test)]
+fn expect_read_config(transport: crate::mock::MockTransport) -> crate::mock::MockTransport {
+    use crate::mock::frame;
+
+    transport
+        .expect(frame(1, 0x72, &[]))
+        .respond(frame(1, 0x72, &[1, 7]))
+        .expect(frame(1, 0x79, &[]))
+        .respond(frame(1, 0x79, &[0x02, 0, 6]))
+        .expect(frame(1, 0x77, &[]))
+        .respond(frame(1, 0x77, &[30]))
+        .expect(frame(1, 0x75, &[]))
+        .respond(frame(1, 0x75, &[0]))
+        .expect(frame(1, 0x63, &[]))
+        .respond(frame(1, 0x63, &[6]))
+        .expect(frame(1, 0x6A, &[]))
+        .respond(frame(1, 0x6A, &[0x11]))
+        .expect(frame(1, 0x68, &[]))
+        .timeout()
+        .expect(frame(1, 0x8E, &[]))
+        .respond(frame(1, 0x8E, &[0x8D]))
+}
+
+#[cfg(test)]
+fn mock_config() -> ReaderConfig {
+    use crate::protocol::FrequencyRegion;
+
+    ReaderConfig {
+        firmware_version: (1, 7),
+        region: RegionConfig::Standard {
+            region: FrequencyRegion::ETSI,
+            start_channel: 0,
+            end_channel: 6,
+        },
+        output_power: vec![30, 30],
+        work_antenna: 0,
+        antenna_detector: -6,
+        beeper_mode: None,
+        link_profile: None,
+        identifier: None,
+        fast_tid: Some(true),
+        address: 1,
+    }
+}
+
+#[test]
+fn test_read_config() {
+    use crate::mock::MockTransport;
+
+    // The link profile is rejected and the identifier times out, so both are left unset.
+    let transport = expect_read_config(MockTransport::new());
+    let mut reader = Reader::from_transport(transport, 1, 2).unwrap();
+    assert_eq!(reader.read_config().unwrap(), mock_config());
+    assert_eq!(reader.port.current_timeout(), Some(READ_TIMEOUT));
+}
+
+#[test]
+fn test_apply_config() {
+    use crate::mock::{frame, MockTransport};
+
+    // The address change must come last, after every other setting has been written.
+    let transport = expect_read_config(MockTransport::new())
+        .expect(frame(1, 0x76, &[20, 25]))
+        .respond(frame(1, 0x76, &[0x10]))
+        .expect(frame(1, 0x7A, &[0x00]))
+        .respond(frame(1, 0x7A, &[0x10]))
+        .expect(frame(1, 0x73, &[2]))
+        .respond(frame(1, 0x73, &[0x10]));
+    let mut reader = Reader::from_transport(transport, 1, 2).unwrap();
+    let target = ReaderConfig {
+        output_power: vec![20, 25],
+        beeper_mode: Some(BeeperMode::Quiet),
+        address: 2,
+        ..mock_config()
+    };
+    assert_eq!(
+        reader.apply_config(&target).unwrap(),
+        vec![
+            ConfigChange::OutputPower(vec![20, 25]),
+            ConfigChange::BeeperMode(BeeperMode::Quiet),
+            ConfigChange::Address(2),
+        ]
+    );
+    assert_eq!(reader.address, 2);
+}
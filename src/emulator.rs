@@ -0,0 +1,808 @@
+//! Software emulator of a reader
+//!
+//! `Emulator` implements the reader side of the protocol against a population of emulated Gen2
+//! tags, so code using `Reader` can be run without hardware. It can be served on a local TCP port
+//! with `spawn_tcp`, or on Linux on a pseudo-terminal with `spawn_pty`, which can be opened with
+//! `Reader::new` like a serial port.
+//!
+//! Only the commands for the firmware version, output power, antennas, temperature, return loss,
+//! frequency region, GPIO, real-time inventory, EPC match and tag access (read, write, lock and
+//! kill) are emulated. Other commands are ignored, as they would be by a reader with older firmware.
+use log::warn;
+use std::convert::TryFrom;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::protocol::{
+    calculate_checksum, Command, CommandType, FrequencyRegion, LockAction, LockTarget, MemoryBank,
+    RegionConfig, ResponseCode, BROADCAST_ADDRESS, START_BYTE,
+};
+
+// The largest EPC memory bank in bytes: CRC, PC and a 496-bit EPC.
+const EPC_BANK_SIZE: usize = 66;
+
+// Highest output power accepted, in dBm.
+const MAX_OUTPUT_POWER: u8 = 33;
+
+/// An emulated Gen2 tag
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmulatedTag {
+    pub epc: Vec<u8>,
+    pub tid: Vec<u8>,
+    pub user: Vec<u8>,
+    pub access_password: [u8; 4],
+    pub kill_password: [u8; 4],
+    /// Raw RSSI of the tag on each antenna port, or `None` if it can't be seen from that port
+    pub rssi: [Option<u8>; 4],
+    /// Killed tags never respond
+    pub killed: bool,
+    locks: [LockAction; 5],
+}
+
+impl EmulatedTag {
+    /// Create a tag with the given EPC
+    ///
+    /// The tag has an Impinj Monza TID, 64 bytes of zeroed user memory and zero passwords. Only
+    /// the TID is locked (permanently). It can be seen from every antenna with an RSSI of -60 dBm.
+    pub fn new(epc: &[u8]) -> EmulatedTag {
+        let mut tag = EmulatedTag {
+            epc: epc.to_vec(),
+            tid: vec![0xE2, 0x80, 0x11, 0x05, 0x20, 0x00, 0, 0, 0, 0, 0, 0],
+            user: vec![0; 64],
+            access_password: [0; 4],
+            kill_password: [0; 4],
+            rssi: [Some(70); 4],
+            killed: false,
+            locks: [LockAction::Open; 5],
+        };
+        tag.set_lock_state(LockTarget::TID, LockAction::PermaLock);
+        tag
+    }
+
+    /// Get the lock state of a memory region
+    pub fn lock_state(&self, target: LockTarget) -> LockAction {
+        self.locks[target as usize - 1]
+    }
+
+    /// Set the lock state of a memory region, bypassing the passwords
+    pub fn set_lock_state(&mut self, target: LockTarget, action: LockAction) {
+        self.locks[target as usize - 1] = action;
+    }
+
+    fn pc(&self) -> [u8; 2] {
+        [((self.epc.len() / 2) as u8) << 3, 0x00]
+    }
+
+    /// The PC, EPC and CRC, as reported with each tag access reply
+    fn reply_data(&self) -> Vec<u8> {
+        let bank = self.bank(MemoryBank::EPC);
+        let mut data = bank[2..].to_vec();
+        data.extend(&bank[..2]);
+        data
+    }
+
+    fn bank(&self, bank: MemoryBank) -> Vec<u8> {
+        match bank {
+            MemoryBank::Reserved => {
+                let mut data = self.kill_password.to_vec();
+                data.extend(&self.access_password);
+                data
+            }
+            MemoryBank::EPC => {
+                let mut data = self.pc().to_vec();
+                data.extend(&self.epc);
+                let mut bank = crc16(&data).to_be_bytes().to_vec();
+                bank.extend(data);
+                bank
+            }
+            MemoryBank::TID => self.tid.clone(),
+            MemoryBank::User => self.user.clone(),
+        }
+    }
+
+    fn set_bank(&mut self, bank: MemoryBank, data: Vec<u8>) {
+        match bank {
+            MemoryBank::Reserved => {
+                self.kill_password.copy_from_slice(&data[0..4]);
+                self.access_password.copy_from_slice(&data[4..8]);
+            }
+            MemoryBank::EPC => {
+                // The EPC length follows the PC, so writing the PC can lengthen or shorten it.
+                let len = (data[2] >> 3) as usize * 2;
+                let mut epc = data[4..].to_vec();
+                epc.resize(len, 0);
+                self.epc = epc;
+            }
+            MemoryBank::TID => self.tid = data,
+            MemoryBank::User => self.user = data,
+        }
+    }
+
+    /// Whether a range of bytes in a memory bank can be accessed
+    fn unlocked(&self, bank: MemoryBank, start: usize, end: usize, secured: bool) -> bool {
+        let targets: Vec<LockTarget> = match bank {
+            MemoryBank::Reserved => [
+                (0, LockTarget::KillPassword),
+                (4, LockTarget::AccessPassword),
+            ]
+            .iter()
+            .filter(|(offset, _)| start < offset + 4 && end > *offset)
+            .map(|(_, target)| *target)
+            .collect(),
+            MemoryBank::EPC => vec![LockTarget::EPC],
+            MemoryBank::TID => vec![LockTarget::TID],
+            MemoryBank::User => vec![LockTarget::User],
+        };
+        targets.iter().all(|target| match self.lock_state(*target) {
+            LockAction::Open | LockAction::PermaUnlock => true,
+            LockAction::Lock => secured,
+            LockAction::PermaLock => false,
+        })
+    }
+
+    /// Check the access password sent with a command
+    ///
+    /// Returns whether the tag is in the secured state. A zero password skips the access step,
+    /// which secures the tag only if its access password is zero.
+    fn access(&self, password: &[u8]) -> Result<bool, ResponseCode> {
+        if password.iter().all(|b| *b == 0) {
+            Ok(self.access_password == [0; 4])
+        } else if password == self.access_password {
+            Ok(true)
+        } else {
+            Err(ResponseCode::AccessFailError)
+        }
+    }
+
+    fn read(
+        &self,
+        password: &[u8],
+        bank: MemoryBank,
+        start_word: u8,
+        words: u8,
+    ) -> Result<Vec<u8>, ResponseCode> {
+        let secured = self.access(password)?;
+        let data = self.bank(bank);
+        let (start, end) = (
+            start_word as usize * 2,
+            (start_word as usize + words as usize) * 2,
+        );
+        if end > data.len() {
+            return Err(ResponseCode::TagReadError);
+        }
+        // Only the passwords are protected from reading.
+        if bank == MemoryBank::Reserved && !self.unlocked(bank, start, end, secured) {
+            return Err(ResponseCode::AccessFailError);
+        }
+        Ok(data[start..end].to_vec())
+    }
+
+    fn write(
+        &mut self,
+        password: &[u8],
+        bank: MemoryBank,
+        start_word: u8,
+        data: &[u8],
+    ) -> ResponseCode {
+        let secured = match self.access(password) {
+            Ok(secured) => secured,
+            Err(code) => return code,
+        };
+        let mut bank_data = self.bank(bank);
+        let (start, end) = (
+            start_word as usize * 2,
+            start_word as usize * 2 + data.len(),
+        );
+        if bank == MemoryBank::EPC {
+            // The CRC is calculated by the tag
+            if start < 2 || end > EPC_BANK_SIZE {
+                return ResponseCode::TagWriteError;
+            }
+            if end > bank_data.len() {
+                bank_data.resize(end, 0);
+            }
+        }
+        if end > bank_data.len() || !self.unlocked(bank, start, end, secured) {
+            return ResponseCode::TagWriteError;
+        }
+        bank_data[start..end].copy_from_slice(data);
+        self.set_bank(bank, bank_data);
+        ResponseCode::Success
+    }
+
+    fn lock(&mut self, password: &[u8], target: LockTarget, action: LockAction) -> ResponseCode {
+        match self.access(password) {
+            Ok(true) if self.access_password != [0; 4] => (),
+            _ => return ResponseCode::AccessFailError,
+        }
+        let current = self.lock_state(target);
+        let permanent = current == LockAction::PermaLock || current == LockAction::PermaUnlock;
+        if permanent && current != action {
+            return ResponseCode::TagLockError;
+        }
+        self.set_lock_state(target, action);
+        ResponseCode::Success
+    }
+
+    fn kill(&mut self, password: &[u8]) -> ResponseCode {
+        if self.kill_password == [0; 4] {
+            ResponseCode::TagKillError
+        } else if password != self.kill_password {
+            ResponseCode::AccessFailError
+        } else {
+            self.killed = true;
+            ResponseCode::Success
+        }
+    }
+}
+
+/// CRC-16 used by Gen2 tags to protect the PC and EPC
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Check a command has at least `len` bytes of data
+fn param(data: &[u8], len: usize) -> Result<&[u8], ResponseCode> {
+    if data.len() < len {
+        return Err(ResponseCode::InvalidParameterError);
+    }
+    Ok(data)
+}
+
+fn success() -> Vec<Vec<u8>> {
+    vec![vec![ResponseCode::Success as u8]]
+}
+
+/// An emulated reader
+pub struct Emulator {
+    /// Reader address
+    pub address: u8,
+    /// Firmware version (major, minor)
+    pub firmware_version: (u8, u8),
+    /// Temperature in celsius
+    pub temperature: i8,
+    /// Return loss of each antenna port in dB - ports with no antenna have a low return loss
+    pub return_loss: Vec<u8>,
+    /// Tags in the field
+    pub tags: Vec<EmulatedTag>,
+    /// Delay between the replies to a command, as a real reader takes time to read each tag
+    pub reply_interval: Duration,
+    /// Levels of the GPIO1 and GPIO2 input pins
+    pub gpio_inputs: [bool; 2],
+    output_power: Vec<u8>,
+    work_antenna: u8,
+    detector_threshold: u8,
+    region: RegionConfig,
+    hop: usize,
+    epc_match: Option<Vec<u8>>,
+}
+
+impl Emulator {
+    /// Create an emulated reader at address 1, with no tags in the field
+    ///
+    /// Panics if `antenna_count` isn't between 1 and 4.
+    pub fn new(antenna_count: u8) -> Emulator {
+        assert!(
+            (1..=4).contains(&antenna_count),
+            "Invalid antenna count {}",
+            antenna_count
+        );
+        Emulator {
+            address: 1,
+            firmware_version: (1, 7),
+            temperature: 30,
+            return_loss: vec![20; antenna_count as usize],
+            tags: Vec::new(),
+            reply_interval: Duration::from_millis(0),
+            gpio_inputs: [false; 2],
+            output_power: vec![30; antenna_count as usize],
+            work_antenna: 0,
+            detector_threshold: 0,
+            region: RegionConfig::Standard {
+                region: FrequencyRegion::ETSI,
+                start_channel: 0,
+                end_channel: 6,
+            },
+            hop: 0,
+            epc_match: None,
+        }
+    }
+
+    /// Handle all of the complete requests in `input`
+    ///
+    /// Requests are removed from `input` as they're handled, along with any bytes which aren't
+    /// part of a valid request. Returns the replies to send.
+    pub fn process(&mut self, input: &mut Vec<u8>) -> Vec<u8> {
+        let mut output = Vec::new();
+        loop {
+            match input.iter().position(|b| *b == START_BYTE) {
+                Some(pos) => {
+                    input.drain(..pos);
+                }
+                None => {
+                    input.clear();
+                    return output;
+                }
+            }
+            if input.len() < 2 {
+                return output;
+            }
+            let len = input[1] as usize + 2;
+            if input.len() < len {
+                return output;
+            }
+            if input[1] < 3 || input[len - 1] != calculate_checksum(&input[..len - 1]) {
+                input.remove(0);
+                continue;
+            }
+            let request: Vec<u8> = input.drain(..len).collect();
+            output.extend(self.handle(request[2], request[3], &request[4..len - 1]));
+        }
+    }
+
+    fn handle(&mut self, address: u8, command: u8, data: &[u8]) -> Vec<u8> {
+        if address != self.address && address != BROADCAST_ADDRESS {
+            return Vec::new();
+        }
+        let command = match CommandType::try_from(command) {
+            Ok(command) => command,
+            Err(_) => return Vec::new(),
+        };
+        // Replies come from the address the request was sent to, even if it has been changed.
+        let address = self.address;
+        let replies = self
+            .reply(command, data)
+            .unwrap_or_else(|code| vec![vec![code as u8]]);
+        replies
+            .into_iter()
+            .flat_map(|data| {
+                Command {
+                    address,
+                    command,
+                    data,
+                }
+                .to_bytes()
+            })
+            .collect()
+    }
+
+    fn reply(&mut self, command: CommandType, data: &[u8]) -> Result<Vec<Vec<u8>>, ResponseCode> {
+        let antenna_count = self.output_power.len();
+        match command {
+            CommandType::Reset | CommandType::SetUARTBaudRate | CommandType::SetBeeperMode => {
+                Ok(success())
+            }
+            CommandType::GetFirmwareVersion => {
+                Ok(vec![vec![self.firmware_version.0, self.firmware_version.1]])
+            }
+            CommandType::SetReaderAddress => {
+                let address = param(data, 1)?[0];
+                if address == BROADCAST_ADDRESS {
+                    return Err(ResponseCode::InvalidReaderAddressError);
+                }
+                self.address = address;
+                Ok(success())
+            }
+            CommandType::SetWorkAntenna => {
+                let antenna = param(data, 1)?[0];
+                if antenna as usize >= antenna_count {
+                    return Err(ResponseCode::InvalidAntennaIDError);
+                }
+                self.work_antenna = antenna;
+                Ok(success())
+            }
+            CommandType::GetWorkAntenna => Ok(vec![vec![self.work_antenna]]),
+            CommandType::SetOutputPower | CommandType::SetTemporaryOutputPower => {
+                if data.len() != 1 && data.len() != antenna_count {
+                    return Err(ResponseCode::InvalidParameterError);
+                }
+                if data.iter().any(|power| *power > MAX_OUTPUT_POWER) {
+                    return Err(ResponseCode::OutputPowerOutOfRangeError);
+                }
+                self.output_power = data.iter().cycle().take(antenna_count).cloned().collect();
+                Ok(success())
+            }
+            CommandType::GetOutputPower => {
+                // All antennas are reported as one if they're the same
+                if self.output_power.iter().all(|p| *p == self.output_power[0]) {
+                    Ok(vec![vec![self.output_power[0]]])
+                } else {
+                    Ok(vec![self.output_power.clone()])
+                }
+            }
+            CommandType::SetFrequencyRegion => {
+                let region = RegionConfig::from_bytes(data)
+                    .map_err(|_| ResponseCode::InvalidFrequencyRegionError)?;
+                region.validate().map_err(|e| match e {
+                    Error::Protocol(code) => code,
+                    _ => ResponseCode::InvalidParameterError,
+                })?;
+                self.region = region;
+                Ok(success())
+            }
+            CommandType::GetFrequencyRegion => Ok(vec![self.region.to_bytes()]),
+            CommandType::GetReaderTemperature => Ok(vec![vec![
+                if self.temperature < 0 { 0x00 } else { 0x01 },
+                self.temperature.unsigned_abs(),
+            ]]),
+            CommandType::SetAntConnectionDetector => {
+                self.detector_threshold = param(data, 1)?[0];
+                Ok(success())
+            }
+            CommandType::GetAntConnectionDetector => Ok(vec![vec![self.detector_threshold]]),
+            CommandType::ReadGPIOValue => Ok(vec![vec![
+                self.gpio_inputs[0] as u8,
+                self.gpio_inputs[1] as u8,
+            ]]),
+            CommandType::WriteGPIOValue => match param(data, 2)?[0] {
+                0x03 | 0x04 => Ok(success()),
+                _ => Err(ResponseCode::InvalidParameterError),
+            },
+            CommandType::GetRFPortReturnLoss => {
+                param(data, 1)?;
+                Ok(vec![vec![self.return_loss[self.work_antenna as usize]]])
+            }
+            CommandType::RealTimeInventory => self.inventory(param(data, 1)?[0]),
+            CommandType::SetAccessEPCMatch => match param(data, 1)?[0] {
+                0x00 => {
+                    let len = param(data, 2)?[1] as usize;
+                    if len == 0 || data.len() != len + 2 {
+                        return Err(ResponseCode::EPCMatchLenError);
+                    }
+                    self.epc_match = Some(data[2..].to_vec());
+                    Ok(success())
+                }
+                0x01 => {
+                    self.epc_match = None;
+                    Ok(success())
+                }
+                _ => Err(ResponseCode::InvalidEPCMatchModeError),
+            },
+            CommandType::GetAccessEPCMatch => match self.epc_match {
+                Some(ref epc) => {
+                    let mut reply = vec![0x00, epc.len() as u8];
+                    reply.extend(epc);
+                    Ok(vec![reply])
+                }
+                None => Ok(vec![vec![0x01]]),
+            },
+            CommandType::Read => self.read(param(data, 7)?),
+            CommandType::Write => self.write(param(data, 8)?),
+            CommandType::Lock => self.lock(param(data, 6)?),
+            CommandType::Kill => self.kill(param(data, 4)?),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// The frequency and antenna byte for the next reply, hopping between channels
+    fn frequency_antenna(&mut self) -> u8 {
+        let channel = match self.region {
+            RegionConfig::Standard {
+                start_channel,
+                end_channel,
+                ..
+            } => start_channel + (self.hop % (end_channel - start_channel + 1) as usize) as u8,
+            RegionConfig::UserDefined { .. } => 0,
+        };
+        self.hop = self.hop.wrapping_add(1);
+        channel << 2 | self.work_antenna
+    }
+
+    /// Tags which can be seen from the working antenna
+    fn visible_tags(&self) -> Result<Vec<usize>, ResponseCode> {
+        let antenna = self.work_antenna as usize;
+        if self.detector_threshold != 0 && self.return_loss[antenna] < self.detector_threshold {
+            return Err(ResponseCode::AntennaMissingError);
+        }
+        Ok((0..self.tags.len())
+            .filter(|i| !self.tags[*i].killed && self.tags[*i].rssi[antenna].is_some())
+            .collect())
+    }
+
+    /// Tags which tag access commands act on, taking the EPC match into account
+    fn matched_tags(&self) -> Result<Vec<usize>, ResponseCode> {
+        let tags: Vec<usize> = self
+            .visible_tags()?
+            .into_iter()
+            .filter(|i| match self.epc_match {
+                Some(ref epc) => self.tags[*i].epc == *epc,
+                None => true,
+            })
+            .collect();
+        if tags.is_empty() {
+            return Err(ResponseCode::NoTagError);
+        }
+        Ok(tags)
+    }
+
+    fn inventory(&mut self, repeat: u8) -> Result<Vec<Vec<u8>>, ResponseCode> {
+        let tags = self.visible_tags()?;
+        let antenna = self.work_antenna as usize;
+        let mut replies = Vec::new();
+        // 255 asks the reader to pick the number of rounds itself
+        let rounds = if repeat == 255 { 1 } else { repeat.max(1) };
+        for _ in 0..rounds {
+            let frequency_antenna = self.frequency_antenna();
+            for i in &tags {
+                let tag = &self.tags[*i];
+                let mut reply = vec![frequency_antenna];
+                reply.extend(&tag.pc());
+                reply.extend(&tag.epc);
+                reply.push(tag.rssi[antenna].unwrap());
+                replies.push(reply);
+            }
+        }
+        let reads = replies.len() as u32;
+        let mut summary = vec![self.work_antenna];
+        summary.extend(&(reads.min(u32::from(u16::MAX)) as u16).to_be_bytes());
+        summary.extend(&reads.to_be_bytes());
+        replies.push(summary);
+        Ok(replies)
+    }
+
+    /// Build a tag access reply
+    fn access_reply(&mut self, tag_count: usize, tag: usize, data: &[u8], status: u8) -> Vec<u8> {
+        let frequency_antenna = self.frequency_antenna();
+        let tag_data = self.tags[tag].reply_data();
+        let mut reply = (tag_count as u16).to_be_bytes().to_vec();
+        reply.push((tag_data.len() + data.len()) as u8);
+        reply.extend(tag_data);
+        reply.extend(data);
+        reply.extend(&[status, frequency_antenna, 1]);
+        reply
+    }
+
+    fn read(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>, ResponseCode> {
+        let bank =
+            MemoryBank::try_from(data[0]).map_err(|_| ResponseCode::MemBankOutOfRangeError)?;
+        let (start, words, password) = (data[1], data[2], &data[3..7]);
+        if words == 0 {
+            return Err(ResponseCode::InvalidParameterError);
+        }
+        // Tags which fail the read don't reply, so the read fails if none succeed.
+        let mut results = Vec::new();
+        let mut error = ResponseCode::TagReadError;
+        for i in self.matched_tags()? {
+            match self.tags[i].read(password, bank, start, words) {
+                Ok(data) => results.push((i, data)),
+                Err(code) => error = code,
+            }
+        }
+        if results.is_empty() {
+            return Err(error);
+        }
+        let count = results.len();
+        Ok(results
+            .into_iter()
+            .map(|(i, data)| self.access_reply(count, i, &data, data.len() as u8))
+            .collect())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>, ResponseCode> {
+        let password = &data[0..4];
+        let bank =
+            MemoryBank::try_from(data[4]).map_err(|_| ResponseCode::MemBankOutOfRangeError)?;
+        let (start, words) = (data[5], data[6] as usize);
+        if words == 0 || data.len() != 7 + words * 2 {
+            return Err(ResponseCode::InvalidParameterError);
+        }
+        let tags = self.matched_tags()?;
+        Ok(tags
+            .iter()
+            .map(|i| {
+                let status = self.tags[*i].write(password, bank, start, &data[7..]);
+                self.access_reply(tags.len(), *i, &[], status as u8)
+            })
+            .collect())
+    }
+
+    fn lock(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>, ResponseCode> {
+        let password = &data[0..4];
+        let target =
+            LockTarget::try_from(data[4]).map_err(|_| ResponseCode::LockRegionOutOfRangeError)?;
+        let action =
+            LockAction::try_from(data[5]).map_err(|_| ResponseCode::LockTypeOutOfRangeError)?;
+        let tags = self.matched_tags()?;
+        Ok(tags
+            .iter()
+            .map(|i| {
+                let status = self.tags[*i].lock(password, target, action);
+                self.access_reply(tags.len(), *i, &[], status as u8)
+            })
+            .collect())
+    }
+
+    fn kill(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>, ResponseCode> {
+        let tags = self.matched_tags()?;
+        Ok(tags
+            .iter()
+            .map(|i| {
+                let status = self.tags[*i].kill(&data[0..4]);
+                self.access_reply(tags.len(), *i, &[], status as u8)
+            })
+            .collect())
+    }
+}
+
+/// Serve an emulator on a stream until it's closed
+pub fn serve<S: Read + Write>(emulator: &Mutex<Emulator>, mut stream: S) -> io::Result<()> {
+    let mut input = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = match stream.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        input.extend(&buf[..len]);
+        let (output, interval) = {
+            let mut emulator = emulator.lock().unwrap_or_else(|e| e.into_inner());
+            (emulator.process(&mut input), emulator.reply_interval)
+        };
+        write_replies(&mut stream, &output, interval)?;
+    }
+}
+
+/// Write replies to a stream, pausing for `interval` between each one
+fn write_replies<S: Write>(
+    stream: &mut S,
+    mut output: &[u8],
+    interval: Duration,
+) -> io::Result<()> {
+    while !output.is_empty() {
+        let (reply, rest) = output.split_at(output[1] as usize + 2);
+        stream.write_all(reply)?;
+        stream.flush()?;
+        output = rest;
+        if !output.is_empty() {
+            thread::sleep(interval);
+        }
+    }
+    Ok(())
+}
+
+/// Serve an emulator on a local TCP port, returning the address to connect to
+///
+/// Connections are served one at a time in a background thread, as by the Ethernet to serial
+/// bridge of a real reader.
+pub fn spawn_tcp(emulator: Arc<Mutex<Emulator>>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|stream| serve(&emulator, stream)) {
+                warn!("Emulator connection failed: {}", e);
+            }
+        }
+    });
+    Ok(addr)
+}
+
+/// Serve an emulator on a pseudo-terminal, returning the path of the terminal to open
+///
+/// The emulator is served in a background thread, and the terminal stays open for the life of
+/// the process.
+#[cfg(all(target_os = "linux", feature = "emulator"))]
+pub fn spawn_pty(emulator: Arc<Mutex<Emulator>>) -> io::Result<std::path::PathBuf> {
+    use std::fs::{self, File};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::{mem, ptr};
+
+    let (mut master, mut slave) = (0, 0);
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+
+    // Put the terminal in raw mode so the line discipline doesn't alter the protocol
+    unsafe {
+        let mut termios = mem::zeroed();
+        if libc::tcgetattr(slave.as_raw_fd(), &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        if libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    let path = fs::read_link(format!("/proc/self/fd/{}", slave.as_raw_fd()))?;
+
+    thread::spawn(move || {
+        // Reads from the master fail once the last handle to the terminal is closed, so keep one
+        // open in case the reader is reopened.
+        let _slave = slave;
+        if let Err(e) = serve(&emulator, master) {
+            warn!("Emulator terminal failed: {}", e);
+        }
+    });
+    Ok(path)
+}
+
+#[test]
+fn test_emulator_tcp() {
+    use crate::protocol::KillPassword;
+    use crate::Reader;
+
+    let mut emulator = Emulator::new(2);
+    emulator.tags.push(EmulatedTag::new(&[
+        0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    ]));
+    let mut tag = EmulatedTag::new(&[0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02]);
+    tag.access_password = [0, 0, 0, 1];
+    tag.kill_password = [0, 0, 0, 2];
+    tag.rssi = [Some(100), None, None, None];
+    emulator.tags.push(tag);
+    let emulator = Arc::new(Mutex::new(emulator));
+
+    let addr = spawn_tcp(emulator.clone()).unwrap();
+    let mut reader = Reader::connect_tcp(addr, 1).unwrap();
+    let capabilities = reader.capabilities().unwrap().clone();
+    assert_eq!(capabilities.antenna_count, 2);
+    assert_eq!(capabilities.firmware_version, (1, 7));
+    assert!(!capabilities.fast_tid);
+
+    let result = reader.real_time_inventory(1).unwrap();
+    assert_eq!(result.items.len(), 2);
+    assert_eq!(result.items[1].epc, vec![0x30, 0, 0, 0, 0, 0, 0, 0x02]);
+    assert_eq!(result.items[1].rssi, -29);
+    reader.set_work_antenna(1).unwrap();
+    assert_eq!(reader.real_time_inventory(1).unwrap().items.len(), 1);
+    reader.set_work_antenna(0).unwrap();
+
+    let epc = vec![0x30, 0, 0, 0, 0, 0, 0, 0x02];
+    let password = [0, 0, 0, 1];
+    reader
+        .with_epc_match(&epc, |reader| {
+            let results = reader.write(MemoryBank::User, &password, 0, &[0x12, 0x34])?;
+            assert_eq!(results[0].status, ResponseCode::Success);
+            let results = reader.lock(LockTarget::User, LockAction::Lock, &password)?;
+            assert_eq!(results[0].status, ResponseCode::Success);
+            let results = reader.write(MemoryBank::User, &[0; 4], 0, &[0x56, 0x78])?;
+            assert_eq!(results[0].status, ResponseCode::TagWriteError);
+            let results = reader.read(MemoryBank::User, &[0; 4], 0, 1)?;
+            assert_eq!(results[0].data, vec![0x12, 0x34]);
+            let results = reader.kill(KillPassword::new(2).unwrap())?;
+            assert_eq!(results[0].status, ResponseCode::Success);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(reader.real_time_inventory(1).unwrap().items.len(), 1);
+    assert!(emulator.lock().unwrap().tags[1].killed);
+}
+
+#[cfg(all(target_os = "linux", feature = "emulator"))]
+#[test]
+fn test_emulator_pty() {
+    let emulator = Arc::new(Mutex::new(Emulator::new(4)));
+    let path = spawn_pty(emulator).unwrap();
+    let mut reader = crate::Reader::new(path.to_str().unwrap(), 1, 4).unwrap();
+    assert_eq!(reader.get_version().unwrap(), (1, 7));
+    assert_eq!(reader.get_temperature().unwrap(), 30);
+    assert_eq!(reader.measure_return_loss(865.0).unwrap(), -20);
+}
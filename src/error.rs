@@ -1,7 +1,9 @@
-///! Error types
+//! Error types
+#![allow(non_local_definitions)]
+
 use std::io;
 use failure::Fail;
-use crate::protocol::{ResponseCode, CommandType};
+use crate::protocol::{ResponseCode, CommandType, FrequencyRegion};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -11,8 +13,10 @@ pub enum Error {
     Io(#[fail(cause)] io::Error),
     #[fail(display="Transient error communicating with tag: {:?}", _0)]
     Communication(ResponseCode),
-    #[fail(display="Error returned from tag: {:?}", _0)]
+    #[fail(display="Error returned from reader: {:?}", _0)]
     Protocol(ResponseCode),
+    #[fail(display="Not supported by this reader: {}", _0)]
+    Unsupported(String),
     #[fail(display="Program error: {}", _0)]
     Program(String),
 }
@@ -47,10 +51,25 @@ impl From<num_enum::TryFromPrimitiveError<CommandType>> for Error {
     }
 }
 
+impl From<num_enum::TryFromPrimitiveError<FrequencyRegion>> for Error {
+    fn from(e: num_enum::TryFromPrimitiveError<FrequencyRegion>) -> Error {
+        Error::Program(format!("Error parsing frequency region: {:?}", e))
+    }
+}
+
 impl From<ResponseCode> for Error {
     fn from(e: ResponseCode) -> Error {
         match e {
-            other => Error::Program(format!("Invalid status response: {:?}", other))
+            ResponseCode::TagInventoryError
+            | ResponseCode::TagReadError
+            | ResponseCode::TagWriteError
+            | ResponseCode::TagLockError
+            | ResponseCode::TagKillError
+            | ResponseCode::NoTagError
+            | ResponseCode::InventoryOKAccessFailError
+            | ResponseCode::AccessFailError
+            | ResponseCode::FailToGetRN16Error => Error::Communication(e),
+            other => Error::Protocol(other),
         }
     }
 }
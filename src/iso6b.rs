@@ -0,0 +1,163 @@
+//! ISO 18000-6B tag support
+//!
+//! 6B tags are addressed by their 8-byte UID, and their memory is a flat array of bytes which can
+//! be individually locked.
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+use crate::operation;
+use crate::transport::Transport;
+use crate::Reader;
+
+/// An ISO 18000-6B tag UID
+pub type Uid = [u8; 8];
+
+/// A 6B tag found during inventory
+#[derive(PartialEq, Debug)]
+pub struct Iso6bTag {
+    /// Antenna tag was read on
+    pub antenna: u8,
+    /// Tag UID
+    pub uid: Uid,
+}
+
+/// The result of a 6B inventory operation
+#[derive(PartialEq, Debug)]
+pub struct Iso6bInventoryResult {
+    /// Antenna used
+    pub antenna: u8,
+    /// List of tags found
+    pub tags: Vec<Iso6bTag>,
+}
+
+/// The result of a 6B read operation
+#[derive(PartialEq, Debug)]
+pub struct Iso6bReadResult {
+    /// Antenna tag was read on
+    pub antenna: u8,
+    pub data: Vec<u8>,
+}
+
+/// The result of a 6B write operation
+#[derive(PartialEq, Debug)]
+pub struct Iso6bWriteResult {
+    /// Antenna tag was written on
+    pub antenna: u8,
+    /// Number of bytes successfully written
+    pub written: u8,
+}
+
+/// The outcome of locking a byte on a 6B tag
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Iso6bLockStatus {
+    Locked,
+    AlreadyLocked,
+    Failed,
+}
+
+impl Iso6bTag {
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<Iso6bTag> {
+        if data.len() != 9 {
+            return Err(Error::Program(format!("Invalid 6B tag packet: {:?}", data)));
+        }
+        Ok(Iso6bTag {
+            antenna: data[0],
+            uid: data[1..9].try_into().unwrap(),
+        })
+    }
+}
+
+impl<T: Transport> Reader<T> {
+    /// Inventory ISO 18000-6B tags on the selected antenna
+    pub fn inventory_6b(&mut self) -> Result<Iso6bInventoryResult> {
+        self.run(operation::inventory_6b())
+    }
+
+    /// Read `length` bytes from a 6B tag, starting at byte `start`
+    pub fn read_6b(&mut self, uid: &Uid, start: u8, length: u8) -> Result<Iso6bReadResult> {
+        self.run(operation::read_6b(uid, start, length))
+    }
+
+    /// Write bytes to a 6B tag, starting at byte `start`
+    ///
+    /// Check `written` in the result, as the write may stop early if it reaches a locked byte.
+    pub fn write_6b(&mut self, uid: &Uid, start: u8, data: &[u8]) -> Result<Iso6bWriteResult> {
+        self.run(operation::write_6b(uid, start, data)?)
+    }
+
+    /// Permanently lock the byte at `address` on a 6B tag
+    pub fn lock_6b(&mut self, uid: &Uid, address: u8) -> Result<Iso6bLockStatus> {
+        self.run(operation::lock_6b(uid, address))
+    }
+
+    /// Query whether the byte at `address` on a 6B tag is locked
+    pub fn query_lock_6b(&mut self, uid: &Uid, address: u8) -> Result<bool> {
+        self.run(operation::query_lock_6b(uid, address))
+    }
+}
+
+#[test]
+fn test_iso6b_tag() {
+    let tag = Iso6bTag::from_bytes(&[1, 0xE0, 0x04, 0x01, 0x00, 0x12, 0x34, 0x56, 0x78]).unwrap();
+    assert_eq!(tag.antenna, 1);
+    assert_eq!(tag.uid, [0xE0, 0x04, 0x01, 0x00, 0x12, 0x34, 0x56, 0x78]);
+    assert!(Iso6bTag::from_bytes(&[1, 2]).is_err());
+}
+
+#[cfg(test)]
+const TEST_UID: Uid = [0xE0, 0x04, 0x01, 0x00, 0x12, 0x34, 0x56, 0x78];
+
+#[test]
+fn test_read_6b() {
+    use crate::mock::{frame, MockTransport};
+
+    let transport = MockTransport::new()
+        .expect(frame(1, 0xB1, &[&TEST_UID[..], &[4, 2]].concat()))
+        .respond(frame(1, 0xB1, &[1, 0xAB, 0xCD]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    let result = reader.read_6b(&TEST_UID, 4, 2).unwrap();
+    assert_eq!(result.antenna, 1);
+    assert_eq!(result.data, vec![0xAB, 0xCD]);
+}
+
+#[test]
+fn test_write_6b() {
+    use crate::mock::{frame, MockTransport};
+
+    let transport = MockTransport::new()
+        .expect(frame(1, 0xB2, &[&TEST_UID[..], &[4, 3, 1, 2, 3]].concat()))
+        .respond(frame(1, 0xB2, &[1, 3]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    let result = reader.write_6b(&TEST_UID, 4, &[1, 2, 3]).unwrap();
+    assert_eq!(result.antenna, 1);
+    assert_eq!(result.written, 3);
+    assert!(reader.write_6b(&TEST_UID, 0, &[0; 256]).is_err());
+}
+
+#[test]
+fn test_lock_6b() {
+    use crate::mock::{frame, MockTransport};
+
+    let transport = MockTransport::new()
+        .expect(frame(1, 0xB3, &[&TEST_UID[..], &[7]].concat()))
+        .respond(frame(1, 0xB3, &[1, 0xFE]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    assert_eq!(
+        reader.lock_6b(&TEST_UID, 7).unwrap(),
+        Iso6bLockStatus::AlreadyLocked
+    );
+}
+
+#[test]
+fn test_query_lock_6b() {
+    use crate::mock::{frame, MockTransport};
+
+    let transport = MockTransport::new()
+        .expect(frame(1, 0xB4, &[&TEST_UID[..], &[7]].concat()))
+        .respond(frame(1, 0xB4, &[1, 0x00]))
+        .expect(frame(1, 0xB4, &[&TEST_UID[..], &[8]].concat()))
+        .respond(frame(1, 0xB4, &[1, 0xFE]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    assert!(!reader.query_lock_6b(&TEST_UID, 7).unwrap());
+    assert!(reader.query_lock_6b(&TEST_UID, 8).unwrap());
+}
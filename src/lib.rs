@@ -9,6 +9,14 @@
 //!
 //! Examples of the use of this library can be found in the `examples` directory.
 //!
+//! ## Features
+//!
+//! * `async` - an asynchronous `AsyncReader` for use with tokio.
+//! * `emulator` - a software emulator of a reader, for running code without hardware.
+//! * `mock` - a scripted mock transport, for testing code built on `Reader`.
+//! * `serde` - implement `Serialize` and `Deserialize` for `ReaderConfig`, so reader settings can
+//!   be saved to and restored from a file.
+//!
 //! ## Supported Readers
 //!
 //! Unless otherwise noted, the modules listed below are *not tested* with this library, but are
@@ -37,69 +45,193 @@
 
 extern crate bitreader;
 extern crate failure;
+#[cfg(all(target_os = "linux", feature = "emulator"))]
+extern crate libc;
 extern crate log;
 extern crate num_enum;
+#[cfg(feature = "serde")]
+extern crate serde;
 extern crate serial;
 
+#[cfg(feature = "async")]
+pub mod async_reader;
+pub mod capabilities;
+pub mod config;
+#[cfg(any(test, feature = "emulator"))]
+pub mod emulator;
 pub mod error;
+pub mod iso6b;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod operation;
 pub mod protocol;
+pub mod transport;
 
 use log::{debug, warn};
-use serial::core::prelude::*;
-use std::io::Read;
-use std::iter;
+use std::net::ToSocketAddrs;
+use std::thread;
 use std::time::Duration;
 
-use crate::error::Result;
+use crate::capabilities::ReaderCapabilities;
+use crate::error::{Error, Result};
+use crate::operation::Operation;
 use crate::protocol::{
-    convert_from_frequency, Command, CommandType, InventoryItem, InventoryResult, MemoryBank,
-    ReadResult, Response, START_BYTE, ResponseCode
+    AntennaHealth, BaudRate, BeeperMode, BufferedInventoryResult, Command, CommandType,
+    FastSwitchInventoryResult, GpioInput, GpioOutput, InventoryItem, InventoryResult, KillPassword,
+    KillResult, LinkProfile, LockAction, LockResult, LockTarget, MemoryBank, ReadResult,
+    ReaderIdentifier, RegionConfig, Response, SelectFlag, Session, Target, TriggerEvent,
+    WriteResult, BROADCAST_ADDRESS, START_BYTE,
 };
+use crate::transport::{TcpTransport, Transport};
+
+#[cfg(feature = "async")]
+pub use crate::async_reader::AsyncReader;
 
 // Some operations can be quite slow, especially with a lot of tags around.
 // I've definitely seen operations take longer than 1sec to complete.
-const READ_TIMEOUT: Duration = Duration::from_millis(5000);
+pub(crate) const READ_TIMEOUT: Duration = Duration::from_millis(5000);
+
+// How long to wait when connecting to a reader over the network.
+pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_millis(5000);
+
+// How long to wait for a reader to respond when probing for readers.
+pub(crate) const PROBE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// How long to wait for the reader to acknowledge a baud rate change.
+pub(crate) const BAUD_RATE_CHANGE_TIMEOUT: Duration = Duration::from_millis(500);
 
 /// Invelion reader
-pub struct Reader {
-    port: serial::SystemPort,
+///
+/// This is generic over the `Transport` used to talk to the reader, which is a local serial port
+/// by default.
+pub struct Reader<T = serial::SystemPort> {
+    port: T,
     antenna_count: usize,
     address: u8,
+    baud_rate: BaudRate,
+    beeper_mode: Option<BeeperMode>,
+    fast_tid: bool,
+    capabilities: Option<ReaderCapabilities>,
 }
 
 impl Reader {
     /// Create the object and connect to the serial port
     ///
     /// `port` should be the name of a serial port device.
-    /// `address` is the address of the reader, which is usually 1.
-    /// `antenna_count` is the number of antenna ports the reader has.
+    /// `address` is the address of the reader, which is usually 1. The broadcast address (0xFF)
+    /// can also be used if there's only one reader connected.
+    /// `antenna_count` is the number of antenna ports the reader has. Use `Reader::open` to
+    /// detect this instead.
     pub fn new(port: &str, address: u8, antenna_count: u8) -> Result<Reader> {
+        Reader::with_baud_rate(port, address, antenna_count, BaudRate::Baud115200)
+    }
+
+    /// Create the object and connect to the serial port at the given baud rate
+    ///
+    /// Readers default to 115200 baud, but may have been reconfigured with `set_baud_rate`.
+    pub fn with_baud_rate(
+        port: &str,
+        address: u8,
+        antenna_count: u8,
+        baud_rate: BaudRate,
+    ) -> Result<Reader> {
         let mut port = serial::open(port)
             .map_err(|e| format!("Unable to connect to serial port {}: {:?}", port, e))?;
-        port.reconfigure(&|settings| {
-            try!(settings.set_baud_rate(serial::Baud115200));
-            settings.set_char_size(serial::Bits8);
-            settings.set_parity(serial::ParityNone);
-            settings.set_stop_bits(serial::Stop1);
-            settings.set_flow_control(serial::FlowNone);
-            Ok(())
-        })
-        .map_err(|e| format!("Failed to configure serial port: {}", e))?;
+        port.set_baud_rate(baud_rate)?;
+        let mut reader = Reader::from_transport(port, address, antenna_count)?;
+        reader.baud_rate = baud_rate;
+        Ok(reader)
+    }
+
+    /// Open the reader with the given identifier
+    ///
+    /// Each of the serial ports in `ports` is tried in turn, and the first reader whose
+    /// identifier matches `identifier` is returned. This is useful when several identical
+    /// readers are connected and their device names may change.
+    pub fn open_by_identifier(
+        ports: &[&str],
+        address: u8,
+        antenna_count: u8,
+        identifier: &ReaderIdentifier,
+    ) -> Result<Reader> {
+        for port in ports {
+            let mut reader = match Reader::new(port, address, antenna_count) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    debug!("Skipping serial port {}: {}", port, e);
+                    continue;
+                }
+            };
+            reader.set_timeout(PROBE_TIMEOUT)?;
+            match reader.get_reader_identifier() {
+                Ok(ref id) if id == identifier => {
+                    reader.set_timeout(READ_TIMEOUT)?;
+                    return Ok(reader);
+                }
+                Ok(id) => debug!("Reader on {} has identifier {}", port, id),
+                Err(e) => debug!("No reader found on {}: {}", port, e),
+            }
+        }
+        Err(Error::Program(format!(
+            "No reader found with identifier {}",
+            identifier
+        )))
+    }
+}
+
+impl Reader<TcpTransport> {
+    /// Connect to a reader over TCP/IP and detect its capabilities
+    ///
+    /// This is for readers with a built-in Ethernet to serial bridge. `addr` is the address and
+    /// port of the bridge, and `address` is the address of the reader as for `Reader::new`. The
+    /// connection will be re-established automatically if it drops.
+    pub fn connect_tcp<A: ToSocketAddrs>(addr: A, address: u8) -> Result<Reader<TcpTransport>> {
+        let transport = TcpTransport::connect(addr, CONNECT_TIMEOUT)?;
+        let mut reader = Reader::from_transport(transport, address, 1)?;
+        reader.probe_capabilities()?;
+        Ok(reader)
+    }
+}
 
-        port.set_timeout(READ_TIMEOUT)
-            .map_err(|e| format!("Failed to set serial port timeout: {}", e))?;
-        Ok(Reader {
-            port,
+impl<T: Transport> Reader<T> {
+    /// Create the object using an existing transport
+    ///
+    /// `address` and `antenna_count` are as for `Reader::new`. The transport is assumed to be
+    /// running at the reader's default baud rate of 115200.
+    pub fn from_transport(transport: T, address: u8, antenna_count: u8) -> Result<Reader<T>> {
+        let mut reader = Reader {
+            port: transport,
             address,
             antenna_count: antenna_count as usize,
-        })
+            baud_rate: BaudRate::Baud115200,
+            beeper_mode: None,
+            fast_tid: false,
+            capabilities: None,
+        };
+        reader.set_timeout(READ_TIMEOUT)?;
+        Ok(reader)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.port.set_timeout(timeout)
     }
 
-    /// Send a command to the reader
-    fn send(&mut self, cmd: Command) -> Result<()> {
+    /// Send the command for an operation to the reader
+    ///
+    /// Commands which the reader is known not to support are rejected without being sent.
+    fn send<O: Operation>(&mut self, operation: &O) -> Result<()> {
+        if let Some(ref capabilities) = self.capabilities {
+            capabilities.check(operation.command(), operation.data())?;
+        }
+        let cmd = Command {
+            address: self.address,
+            command: operation.command(),
+            data: operation.data().to_vec(),
+        };
         let cmd_bytes = cmd.to_bytes();
         debug!("Send {:?}: {:?}", cmd.command, cmd_bytes);
-        std::io::Write::write(&mut self.port, &cmd_bytes)?;
+        self.port.write_all(&cmd_bytes)?;
+        self.port.flush()?;
         Ok(())
     }
 
@@ -127,24 +259,28 @@ impl Reader {
         let mut len = [0u8; 1];
         std::io::Read::read_exact(&mut self.port, &mut len)?;
         let len = len[0] as usize;
-        let mut response: Vec<u8> = Vec::with_capacity(len + 2);
-        response.extend(&[start, len as u8]);
-        {
-            let reference = self.port.by_ref();
-            reference.take(len as u64).read_to_end(&mut response)?;
+        if len < 3 {
+            return Err(Error::Program(format!("Invalid packet length {}", len)));
         }
+        let mut response: Vec<u8> = vec![0u8; len + 2];
+        response[0] = start;
+        response[1] = len as u8;
+        std::io::Read::read_exact(&mut self.port, &mut response[2..])?;
         debug!("Receive: {:?}", response);
-        Ok(Response::from_bytes(&response)?)
+        Response::from_bytes(&response)
     }
 
     /// Receive a response from the reader
     ///
     /// This will drop packets which don't have the expected command type in case the driver has
-    /// lost sync.
+    /// lost sync, and packets from other readers on a shared (RS-485) bus. If the reader address
+    /// is the broadcast address (0xFF), packets from any reader are accepted.
     fn receive(&mut self, command_type: CommandType) -> Result<Response> {
         loop {
             let packet = self.receive_packet()?;
-            if packet.command == command_type {
+            if self.address != BROADCAST_ADDRESS && packet.address != self.address {
+                warn!("Dropped packet due to incorrect address: {:?}", packet);
+            } else if packet.command == command_type {
                 return Ok(packet);
             } else {
                 warn!("Dropped packet due to incorrect command type: {:?}", packet);
@@ -152,116 +288,346 @@ impl Reader {
         }
     }
 
-    fn exchange(&mut self, command: Command) -> Result<Response> {
-        let command_type = command.command;
-        self.send(command)?;
-        self.receive(command_type)
-    }
-
-    /// Send a command with no parameters and receive a response
-    fn exchange_simple(&mut self, command: CommandType) -> Result<Response> {
-        let cmd = Command {
-            address: self.address,
-            command,
-            data: vec![],
-        };
-        self.exchange(cmd)
+    /// Run an operation, sending its command and handling each reply until the last one
+    fn run<O: Operation>(&mut self, mut operation: O) -> Result<O::Output> {
+        self.send(&operation)?;
+        let mut replies = operation.replies();
+        loop {
+            let response = self.receive(replies.command)?;
+            let last = replies.is_last(&response);
+            operation.reply(response)?;
+            if last {
+                return operation.finish();
+            }
+        }
     }
 
     /// Reset the reader
     pub fn reset(&mut self) -> Result<()> {
-        self.exchange_simple(CommandType::Reset)?;
+        self.run(operation::reset())
+    }
+
+    /// Change the baud rate of the reader's serial port
+    ///
+    /// The local serial port is reconfigured to match, and the link is checked by fetching the
+    /// firmware version. If the reader didn't acknowledge the change and that check fails, the
+    /// local port is returned to the previous baud rate. Once the reader has acknowledged the
+    /// change it is known to be at the new rate, so the local port is left there even if the
+    /// check fails.
+    pub fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<()> {
+        // Check the transport can be reconfigured before changing the reader.
+        let previous = self.baud_rate;
+        self.port.set_baud_rate(previous)?;
+
+        // Not all firmware replies to this command, so only wait briefly for a response.
+        self.set_timeout(BAUD_RATE_CHANGE_TIMEOUT)?;
+        let response = self.run(operation::set_baud_rate(baud_rate));
+        self.set_timeout(READ_TIMEOUT)?;
+        let acknowledged = operation::acknowledged(response)?;
+
+        self.port.set_baud_rate(baud_rate)?;
+        self.baud_rate = baud_rate;
+        if let Err(e) = self.get_version() {
+            if !acknowledged {
+                self.port.set_baud_rate(previous)?;
+                self.baud_rate = previous;
+            }
+            return Err(e);
+        }
         Ok(())
     }
 
+    /// Set the address of the reader
+    ///
+    /// Each reader on a shared RS-485 bus must have a different address from 0x00 to 0xFE.
+    /// Subsequent commands will be sent to the new address.
+    pub fn set_reader_address(&mut self, address: u8) -> Result<()> {
+        self.run(operation::set_reader_address(address)?)?;
+        self.address = address;
+        Ok(())
+    }
+
+    /// Scan a shared RS-485 bus for readers
+    ///
+    /// This probes each address from 0x00 to 0xFE by requesting the firmware version, and returns
+    /// a list of (address, version) tuples for each reader which responded. This takes around
+    /// 25 seconds if there are no readers on the bus.
+    pub fn scan_bus(&mut self) -> Result<Vec<(u8, (u8, u8))>> {
+        let address = self.address;
+        self.set_timeout(PROBE_TIMEOUT)?;
+
+        let mut found = Vec::new();
+        for probe in 0..BROADCAST_ADDRESS {
+            self.address = probe;
+            match self.get_version() {
+                Ok(version) => found.push((probe, version)),
+                Err(Error::Io(_)) => (),
+                Err(e) => warn!("Error probing reader address {}: {}", probe, e),
+            }
+        }
+
+        self.address = address;
+        self.set_timeout(READ_TIMEOUT)?;
+        Ok(found)
+    }
+
     /// Get the firmware version of the reader
     ///
     /// Returns a tuple of (major, minor).
     pub fn get_version(&mut self) -> Result<(u8, u8)> {
-        let response = self.exchange_simple(CommandType::GetFirmwareVersion)?;
-        Ok((response.data[0], response.data[1]))
+        self.run(operation::get_version())
+    }
+
+    /// Set the reader identifier, which is saved to flash
+    pub fn set_reader_identifier(&mut self, identifier: &ReaderIdentifier) -> Result<()> {
+        self.run(operation::set_reader_identifier(identifier))
+    }
+
+    /// Get the reader identifier
+    pub fn get_reader_identifier(&mut self) -> Result<ReaderIdentifier> {
+        self.run(operation::get_reader_identifier())
+    }
+
+    /// Set the Gen2 RF link profile
+    ///
+    /// If the reader doesn't support the profile, this returns the error code reported by the
+    /// reader.
+    pub fn set_link_profile(&mut self, profile: LinkProfile) -> Result<()> {
+        self.run(operation::set_link_profile(profile))
+    }
+
+    /// Get the Gen2 RF link profile
+    pub fn get_link_profile(&mut self) -> Result<LinkProfile> {
+        self.run(operation::get_link_profile())
     }
 
     /// Set the working antenna ID
     ///
     /// `antenna_id` is from 0 to the number of available antennas.
     pub fn set_work_antenna(&mut self, antenna_id: u8) -> Result<()> {
-        let cmd = Command {
-            address: self.address,
-            command: CommandType::SetWorkAntenna,
-            data: vec![antenna_id],
-        };
-        self.exchange(cmd)?;
-        Ok(())
+        self.run(operation::set_work_antenna(antenna_id))
     }
 
     /// Get the working antenna ID
     ///
     /// Returns an ID from 0 to the number of available antennas.
     pub fn get_work_antenna(&mut self) -> Result<u8> {
-        let response = self.exchange_simple(CommandType::GetWorkAntenna)?;
-        Ok(response.data[0])
+        self.run(operation::get_work_antenna())
+    }
+
+    /// Run `f`, then restore the working antenna whether or not it succeeded
+    fn restoring_work_antenna<R, F>(&mut self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Reader<T>) -> Result<R>,
+    {
+        let work_antenna = self.get_work_antenna()?;
+        let result = f(self);
+        let restored = self.set_work_antenna(work_antenna);
+        operation::with_cleanup(result, restored)
     }
 
     /// Get the state of the antenna connection detector for the working antenna
     ///
     /// The value is the detector threshold in dB, or 0 if disabled.
     pub fn get_antenna_connection_detector(&mut self) -> Result<i8> {
-        let response = self.exchange_simple(CommandType::GetAntConnectionDetector)?;
-        Ok(-(response.data[0] as i8))
+        self.run(operation::get_antenna_connection_detector())
+    }
+
+    /// Set the antenna connection detector threshold for the working antenna
+    ///
+    /// `threshold` is the return loss in dB below which the reader considers the antenna to be
+    /// disconnected, as returned by `get_antenna_connection_detector`. Set to 0 to disable the
+    /// detector.
+    pub fn set_antenna_connection_detector(&mut self, threshold: i8) -> Result<()> {
+        self.run(operation::set_antenna_connection_detector(threshold))
+    }
+
+    /// Check which antenna ports have a working antenna connected
+    ///
+    /// Each antenna is selected in turn, and its return loss is measured at `frequency`. An
+    /// antenna is reported as disconnected if its return loss is worse than the connection
+    /// detector threshold or if the reader reports it missing during inventory, and as a poor
+    /// match if its return loss is worse than `poor_match` dB.
+    ///
+    /// The working antenna is restored afterwards, even if the check fails.
+    pub fn antenna_health(&mut self, frequency: f32, poor_match: i8) -> Result<Vec<AntennaHealth>> {
+        self.restoring_work_antenna(|reader| {
+            (0..reader.antenna_count as u8)
+                .map(|antenna| reader.check_antenna_health(antenna, frequency, poor_match))
+                .collect()
+        })
+    }
+
+    fn check_antenna_health(
+        &mut self,
+        antenna: u8,
+        frequency: f32,
+        poor_match: i8,
+    ) -> Result<AntennaHealth> {
+        self.set_work_antenna(antenna)?;
+        let detector_threshold = self.get_antenna_connection_detector()?;
+        let return_loss = self.measure_return_loss(frequency)?;
+
+        let inventory = self.real_time_inventory(1);
+        Ok(AntennaHealth {
+            antenna,
+            status: operation::antenna_status(
+                inventory,
+                return_loss,
+                detector_threshold,
+                poor_match,
+            )?,
+            return_loss,
+            detector_threshold,
+        })
     }
 
     /// Set the output power per antenna and save to flash
     ///
     /// The length of `power` should be the number of antennas, and the value of power
     /// is in dBm (acceptable range is reader-dependent).
+    ///
+    /// Writing to flash frequently may wear it out - use `set_temporary_output_power` if the
+    /// power is changed often.
     pub fn set_output_power(&mut self, power: &[u8]) -> Result<()> {
-        assert_eq!(power.len(), self.antenna_count);
-        let cmd = Command {
-            address: self.address,
-            command: CommandType::SetOutputPower,
-            data: power.to_vec(),
-        };
-        self.exchange(cmd)?;
-        Ok(())
+        self.run(operation::set_output_power(power, self.antenna_count)?)
+    }
+
+    /// Set the output power for all antennas without saving it to flash
+    ///
+    /// `power` is in dBm. The setting is lost when the reader is reset.
+    pub fn set_temporary_output_power(&mut self, power: u8) -> Result<()> {
+        self.run(operation::set_temporary_output_power(power))
+    }
+
+    /// Run `f` with a temporary output power, restoring the previous power afterwards
+    ///
+    /// The previous power is always restored, even if `f` fails. If both `f` and restoring the
+    /// power fail, the error from `f` is returned.
+    ///
+    /// If the antennas have different output powers, they can't be restored with a temporary
+    /// setting, so an error is returned without changing the power or running `f`.
+    pub fn with_temporary_output_power<R, F>(&mut self, power: u8, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Reader<T>) -> Result<R>,
+    {
+        let previous = operation::restorable_output_power(&self.get_output_power()?)?;
+        self.set_temporary_output_power(power)?;
+        let result = f(self);
+        let restored = self.set_temporary_output_power(previous);
+        operation::with_cleanup(result, restored)
     }
 
     /// Get the output power per antenna
     ///
     /// Returns a vector of power for each antenna (in dBm)
     pub fn get_output_power(&mut self) -> Result<Vec<u8>> {
-        let response = self.exchange_simple(CommandType::GetOutputPower)?;
-        if response.data.len() == 1 {
-            // Reader only sends the power once if all antennas are set the same,
-            // so repeat it for consistency.
-            return Ok(iter::repeat(response.data[0])
-                .take(self.antenna_count)
-                .collect());
+        self.run(operation::get_output_power(self.antenna_count))
+    }
+
+    /// Get the frequency region configuration
+    pub fn get_frequency_region(&mut self) -> Result<RegionConfig> {
+        self.run(operation::get_frequency_region())
+    }
+
+    /// Set the frequency region configuration
+    ///
+    /// The configuration is checked before it is sent, returning
+    /// `Error::Protocol(ResponseCode::InvalidFrequencyRegionError)` or
+    /// `Error::Protocol(ResponseCode::InvalidFrequencyRangeError)` if it's invalid.
+    pub fn set_frequency_region(&mut self, config: RegionConfig) -> Result<()> {
+        self.run(operation::set_frequency_region(config)?)
+    }
+
+    /// Read the level of a GPIO input pin
+    pub fn read_gpio(&mut self, input: GpioInput) -> Result<bool> {
+        self.run(operation::read_gpio(input))
+    }
+
+    /// Set the level of a GPIO output pin
+    pub fn write_gpio(&mut self, output: GpioOutput, value: bool) -> Result<()> {
+        self.run(operation::write_gpio(output, value))
+    }
+
+    /// Run real-time inventory whenever a GPIO input is active
+    ///
+    /// This polls `input` every `poll_interval` while idle, and runs `real_time_inventory(repeat)`
+    /// repeatedly while the input is at the `active` level, checking the input between runs.
+    /// Each change of the input and each inventory result is passed to `handler`. This runs
+    /// until `handler` returns false.
+    pub fn triggered_inventory<F>(
+        &mut self,
+        input: GpioInput,
+        active: bool,
+        poll_interval: Duration,
+        repeat: u8,
+        mut handler: F,
+    ) -> Result<()>
+    where
+        F: FnMut(TriggerEvent) -> bool,
+    {
+        let mut triggered = false;
+        loop {
+            let level = self.read_gpio(input)? == active;
+            if let Some(event) = operation::trigger_change(&mut triggered, level) {
+                if !handler(event) {
+                    return Ok(());
+                }
+            }
+            if triggered {
+                let result = self.real_time_inventory(repeat)?;
+                if !handler(TriggerEvent::Inventory(result)) {
+                    return Ok(());
+                }
+            } else {
+                thread::sleep(poll_interval);
+            }
         }
-        Ok(response.data)
+    }
+
+    /// Set when the reader's beeper sounds
+    pub fn set_beeper_mode(&mut self, mode: BeeperMode) -> Result<()> {
+        self.run(operation::set_beeper_mode(mode))?;
+        self.beeper_mode = Some(mode);
+        Ok(())
+    }
+
+    /// Get the beeper mode
+    ///
+    /// The reader has no command to read the beeper mode back, so this returns the mode last
+    /// set with `set_beeper_mode`, or `None` if it hasn't been set since connecting.
+    pub fn beeper_mode(&self) -> Option<BeeperMode> {
+        self.beeper_mode
     }
 
     /// Fetch the temperature of the reader in celsius
     pub fn get_temperature(&mut self) -> Result<i8> {
-        let response = self.exchange_simple(CommandType::GetReaderTemperature)?;
-        let mut temp = response.data[1] as i8;
-        // Datasheet says the first byte is 0x01 if negative, but this doesn't
-        // seem to be correct. Guessing they got that reversed. It's not that cold in here.
-        if response.data[0] == 0x00 {
-            temp = -temp;
-        }
-        Ok(temp)
+        self.run(operation::get_temperature())
     }
 
     /// Measure the return loss in dB of the selected antenna
     pub fn measure_return_loss(&mut self, frequency: f32) -> Result<i8> {
-        let cmd = Command {
-            address: self.address,
-            command: CommandType::GetRFPortReturnLoss,
-            data: vec![convert_from_frequency(frequency)?],
-        };
-        let response = self.exchange(cmd)?;
-        Ok(-(response.data[0] as i8))
+        self.run(operation::measure_return_loss(frequency)?)
+    }
+
+    /// Enable or disable Impinj FastTID
+    ///
+    /// When enabled, Impinj Monza tags return their TID alongside their EPC during inventory,
+    /// which will be available in `InventoryItem::tid`. If `save` is true, the setting is saved to
+    /// flash, otherwise it is lost when the reader is reset.
+    ///
+    /// TIDs are only split from EPCs once FastTID is known to be enabled, either by calling this
+    /// or `get_fast_tid` (which `Reader::open` does when probing the reader's capabilities).
+    pub fn set_fast_tid(&mut self, enabled: bool, save: bool) -> Result<()> {
+        self.run(operation::set_fast_tid(enabled, save))?;
+        self.fast_tid = enabled;
+        Ok(())
+    }
+
+    /// Get whether Impinj FastTID is enabled
+    pub fn get_fast_tid(&mut self) -> Result<bool> {
+        self.fast_tid = self.run(operation::get_fast_tid())?;
+        Ok(self.fast_tid)
     }
 
     /// Start an inventory operation on the selected antenna and return inventory data in real time.
@@ -270,21 +636,103 @@ impl Reader {
     /// (although this is unclear - the datasheet calls this "repeat time"). It can be set to 255
     /// which means the reader will optimise this for speed to allow fast multi-antenna operation.
     pub fn real_time_inventory(&mut self, repeat: u8) -> Result<InventoryResult> {
-        let cmd = Command {
-            address: self.address,
-            command: CommandType::RealTimeInventory,
-            data: vec![repeat],
-        };
-        self.send(cmd)?;
+        self.run(operation::real_time_inventory(repeat, self.fast_tid))
+    }
 
-        let mut tags: Vec<InventoryItem> = Vec::new();
-        loop {
-            let response = self.receive(CommandType::RealTimeInventory)?;
-            if response.data.len() < 8 {
-                return InventoryResult::from_bytes(&response.data, tags);
-            };
-            tags.push(InventoryItem::from_bytes(&response.data)?);
-        }
+    /// Start an inventory operation on the selected antenna with the given Gen2 session and
+    /// target, and return inventory data in real time.
+    ///
+    /// Using session S1-S3 with a single target means each tag will only be read once until its
+    /// inventoried flag decays, which helps to read large tag populations.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - the Gen2 session to inventory in.
+    /// * `target` - the inventoried flag to target.
+    /// * `select` - optionally restrict the inventory based on the tag's SL flag.
+    /// * `phase` - whether to report the RF phase of each tag read.
+    /// * `repeat` - as for `real_time_inventory`.
+    pub fn session_target_inventory(
+        &mut self,
+        session: Session,
+        target: Target,
+        select: Option<SelectFlag>,
+        phase: bool,
+        repeat: u8,
+    ) -> Result<InventoryResult> {
+        self.run(operation::session_target_inventory(
+            session,
+            target,
+            select,
+            phase,
+            repeat,
+            self.fast_tid,
+        ))
+    }
+
+    /// Run an inventory which switches between antennas on the reader itself.
+    ///
+    /// This is much faster than calling `set_work_antenna` and `real_time_inventory` for each
+    /// antenna in turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - up to four `(antenna_id, stay)` pairs, giving the order in which antennas
+    ///   are scanned and the number of inventory rounds to stay on each.
+    /// * `interval` - the delay between switching antennas, in ms.
+    /// * `repeat` - the number of times to repeat the whole sequence.
+    pub fn fast_switch_inventory(
+        &mut self,
+        sequence: &[(u8, u8)],
+        interval: u8,
+        repeat: u8,
+    ) -> Result<FastSwitchInventoryResult> {
+        self.run(operation::fast_switch_inventory(
+            sequence,
+            self.antenna_count,
+            interval,
+            repeat,
+            self.fast_tid,
+        )?)
+    }
+
+    /// Start an inventory operation on the selected antenna, storing the results in the reader's
+    /// buffer.
+    ///
+    /// Tags are deduplicated in the buffer, which makes this better suited than
+    /// `real_time_inventory` to large tag populations. `repeat` is the number of inventory
+    /// rounds to run. Use `get_inventory_buffer` to fetch the tags afterwards.
+    ///
+    /// If no tags are found, the reader doesn't report which antenna was used, so this returns
+    /// `Error::Communication(ResponseCode::NoTagError)` rather than an empty result.
+    pub fn buffered_inventory(&mut self, repeat: u8) -> Result<BufferedInventoryResult> {
+        self.run(operation::buffered_inventory(repeat))
+    }
+
+    /// Get the number of unique tags in the inventory buffer
+    pub fn get_buffer_tag_count(&mut self) -> Result<u16> {
+        self.run(operation::get_buffer_tag_count())
+    }
+
+    /// Fetch the tags in the inventory buffer, leaving the buffer intact
+    pub fn get_inventory_buffer(&mut self) -> Result<Vec<InventoryItem>> {
+        self.run(operation::inventory_buffer(
+            CommandType::GetInventoryBuffer,
+            self.fast_tid,
+        ))
+    }
+
+    /// Fetch the tags in the inventory buffer and clear the buffer
+    pub fn get_and_reset_inventory_buffer(&mut self) -> Result<Vec<InventoryItem>> {
+        self.run(operation::inventory_buffer(
+            CommandType::GetAndResetInventoryBuffer,
+            self.fast_tid,
+        ))
+    }
+
+    /// Clear the inventory buffer
+    pub fn reset_inventory_buffer(&mut self) -> Result<()> {
+        self.run(operation::reset_inventory_buffer())
     }
 
     /// Read data from tags
@@ -306,50 +754,358 @@ impl Reader {
         start: u8,
         length: u8,
     ) -> Result<Vec<ReadResult>> {
-        let mut data = vec![bank as u8, start, length];
-        data.extend(password);
-        let cmd = Command {
-            address: self.address,
-            command: CommandType::Read,
-            data,
-        };
-        self.send(cmd)?;
+        self.run(operation::read(bank, password, start, length))
+    }
 
-        let mut results = Vec::new();
-        loop {
-            let response = self.receive(CommandType::Read)?;
-            if response.status == Some(ResponseCode::NoTagError) {
-                // No tags found
-                return Ok(results);
-            }
-            let (tag_count, packet) = ReadResult::from_bytes(&response.data)?;
-            results.push(packet);
-            if results.len() == tag_count {
-                return Ok(results);
-            }
-        }
+    /// Write data to tags
+    ///
+    /// By default this will issue a write command to all tags within range. It will return a
+    /// WriteResult for each tag which responded - check the `status` of each result to see
+    /// whether the write succeeded on that tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `bank` - the memory bank to write to.
+    /// * `password` - the 4-byte access password, or `[0, 0, 0, 0]` if not set/required.
+    /// * `start_word` - the starting offset of the write, in 2-byte words.
+    /// * `data` - the data to write. This must be a whole number of 2-byte words.
+    pub fn write(
+        &mut self,
+        bank: MemoryBank,
+        password: &[u8],
+        start_word: u8,
+        data: &[u8],
+    ) -> Result<Vec<WriteResult>> {
+        self.run(operation::write(bank, password, start_word, data)?)
     }
 
-    /// (NOT working) set EPC access match mask
+    /// Lock or unlock a memory region on tags
+    ///
+    /// By default this will issue a lock command to all tags within range. It will return a
+    /// LockResult for each tag which responded - check the `status` of each result to see
+    /// whether the lock succeeded on that tag (it will be `ResponseCode::TagLockError` if not).
+    ///
+    /// # Arguments
     ///
-    /// I assume this function restricts commands to act on certain EPC tags but I can't get it to
-    /// work.
+    /// * `target` - the memory region to lock.
+    /// * `action` - the lock operation to apply. Permanent operations cannot be reversed.
+    /// * `password` - the 4-byte access password. Tags must have a non-zero access password to be
+    ///   locked.
+    pub fn lock(
+        &mut self,
+        target: LockTarget,
+        action: LockAction,
+        password: &[u8],
+    ) -> Result<Vec<LockResult>> {
+        self.run(operation::lock(target, action, password))
+    }
+
+    /// Permanently kill a tag
+    ///
+    /// To avoid killing every tag in range, this will refuse to run unless the reader has an EPC
+    /// access match set - use `with_epc_match` to target a single tag. It returns a KillResult
+    /// for each tag which responded - check the `status` of each result to see whether the kill
+    /// succeeded on that tag.
+    pub fn kill(&mut self, password: KillPassword) -> Result<Vec<KillResult>> {
+        let epc_match = self.get_epc_match()?;
+        self.run(operation::kill(password, epc_match)?)
+    }
+
+    /// Set the EPC access match
+    ///
+    /// While a match is set, tag access commands (read, write, lock and kill) will only act on
+    /// tags whose EPC matches `epc`. Pass an empty slice to clear the match.
+    ///
+    /// The match persists until cleared, so prefer `with_epc_match` which clears it afterwards.
     pub fn set_epc_match(&mut self, epc: &[u8]) -> Result<()> {
-        let mode = if epc.is_empty() {
-            0x01 // Clear match
-        } else {
-            0x00
-        };
+        self.run(operation::set_epc_match(epc))
+    }
 
-        let mut data = vec![mode, epc.len() as u8];
-        data.extend(epc);
+    /// Get the EPC access match
+    ///
+    /// Returns the EPC being matched, or `None` if no match is set.
+    pub fn get_epc_match(&mut self) -> Result<Option<Vec<u8>>> {
+        self.run(operation::get_epc_match())
+    }
 
-        let cmd = Command {
-            address: self.address,
-            command: CommandType::SetAccessEPCMatch,
-            data,
-        };
-        self.exchange(cmd)?;
-        Ok(())
+    /// Run `f` with the EPC access match set to `epc`
+    ///
+    /// The match is always cleared afterwards, even if `f` fails. If both `f` and clearing the
+    /// match fail, the error from `f` is returned.
+    pub fn with_epc_match<R, F>(&mut self, epc: &[u8], f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Reader<T>) -> Result<R>,
+    {
+        self.run(operation::set_scoped_epc_match(epc)?)?;
+        let result = f(self);
+        let cleared = self.set_epc_match(&[]);
+        operation::with_cleanup(result, cleared)
+    }
+}
+
+#[cfg(test)]
+use crate::mock::{frame, MockTransport};
+
+#[test]
+fn test_receive_drops_mismatched_packets() {
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x72, &[]))
+        .garbage(&[0x00, 0x12])
+        .respond(frame(2, 0x72, &[2, 0]))
+        .respond(frame(1, 0x75, &[0]))
+        .respond(frame(1, 0x72, &[1, 7]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    assert_eq!(reader.get_version().unwrap(), (1, 7));
+}
+
+#[test]
+fn test_bad_checksum() {
+    let mut corrupted = frame(1, 0x72, &[1, 7]);
+    corrupted[5] ^= 0xFF;
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x72, &[]))
+        .garbage(&corrupted)
+        .expect(frame(1, 0x72, &[]))
+        .respond(frame(1, 0x72, &[1, 7]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    match reader.get_version() {
+        Err(Error::Program(message)) => assert!(message.contains("Bad checksum")),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert_eq!(reader.get_version().unwrap(), (1, 7));
+}
+
+#[test]
+fn test_missing_response_code() {
+    let transport = MockTransport::new()
+        .expect(frame(0x9C, 0x70, &[]))
+        .respond(frame(0x9C, 0x70, &[]));
+    let mut reader = Reader::from_transport(transport, 0x9C, 1).unwrap();
+    match reader.reset() {
+        Err(Error::Program(message)) => assert!(message.contains("Missing response code")),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_read_stops_at_tag_count() {
+    // A single reply with a tag count of one - reading any further would run off the script.
+    let reply = [
+        160, 33, 1, 129, 0, 1, 24, 48, 0, 48, 57, 96, 98, 195, 149, 13, 64, 0, 17, 184, 151, 205,
+        11, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141,
+    ];
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x81, &[1, 2, 6, 0, 0, 0, 0]))
+        .respond(reply.to_vec());
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    let results = reader.read(MemoryBank::EPC, &[0; 4], 2, 6).unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn test_fast_tid_split() {
+    let tag = [
+        0x14, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 0xE2, 0x80, 0x11, 0x05, 0x20,
+        0x00, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 100,
+    ];
+    let summary = [1, 0, 10, 0, 0, 0, 1];
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x89, &[1]))
+        .respond(frame(1, 0x89, &tag))
+        .respond(frame(1, 0x89, &summary))
+        .expect(frame(1, 0x8C, &[0x8D]))
+        .respond(frame(1, 0x8C, &[0x10]))
+        .expect(frame(1, 0x89, &[1]))
+        .respond(frame(1, 0x89, &tag))
+        .respond(frame(1, 0x89, &summary));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+
+    // Without FastTID, long EPCs (such as from XPC tags) must be left intact.
+    let result = reader.real_time_inventory(1).unwrap();
+    assert_eq!(result.items[0].epc.len(), 24);
+    assert_eq!(result.items[0].tid, None);
+
+    reader.set_fast_tid(true, false).unwrap();
+    let result = reader.real_time_inventory(1).unwrap();
+    assert_eq!(result.items[0].epc.len(), 12);
+    assert_eq!(result.items[0].tid.as_ref().map(Vec::len), Some(12));
+}
+
+#[test]
+fn test_set_baud_rate_acknowledged() {
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x71, &[0x03]))
+        .respond(frame(1, 0x71, &[0x10]))
+        .expect(frame(1, 0x72, &[]))
+        .respond(frame(1, 0x72, &[1, 7]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    reader.set_baud_rate(BaudRate::Baud38400).unwrap();
+    assert_eq!(reader.port.current_baud_rate(), Some(BaudRate::Baud38400));
+}
+
+#[test]
+fn test_set_baud_rate_no_reply() {
+    // Some firmware doesn't acknowledge the change, so the driver carries on at the new rate.
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x71, &[0x03]))
+        .timeout()
+        .expect(frame(1, 0x72, &[]))
+        .respond(frame(1, 0x72, &[1, 7]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    reader.set_baud_rate(BaudRate::Baud38400).unwrap();
+    assert_eq!(reader.port.current_baud_rate(), Some(BaudRate::Baud38400));
+}
+
+#[test]
+fn test_set_baud_rate_verify_failed() {
+    // Without an acknowledgement, a failed check means the reader didn't change rate.
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x71, &[0x03]))
+        .timeout()
+        .expect(frame(1, 0x72, &[]))
+        .timeout();
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    assert!(reader.set_baud_rate(BaudRate::Baud38400).is_err());
+    assert_eq!(reader.port.current_baud_rate(), Some(BaudRate::Baud115200));
+
+    // Once the reader has acknowledged the change, it must stay at the new rate.
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x71, &[0x03]))
+        .respond(frame(1, 0x71, &[0x10]))
+        .expect(frame(1, 0x72, &[]))
+        .timeout();
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+    assert!(reader.set_baud_rate(BaudRate::Baud38400).is_err());
+    assert_eq!(reader.port.current_baud_rate(), Some(BaudRate::Baud38400));
+}
+
+#[test]
+fn test_antenna_health() {
+    use crate::protocol::AntennaStatus;
+
+    // Connection detector threshold, return loss and inventory reply for each antenna
+    let antennas: [(u8, u8, &[u8]); 4] = [
+        (0, 20, &[0, 0, 0, 0, 0, 0, 0]),
+        (10, 5, &[1, 0, 0, 0, 0, 0, 0]),
+        (0, 5, &[2, 0, 0, 0, 0, 0, 0]),
+        (0, 20, &[0x22]),
+    ];
+    let mut transport = MockTransport::new()
+        .expect(frame(1, 0x75, &[]))
+        .respond(frame(1, 0x75, &[0]));
+    for (antenna, (threshold, return_loss, inventory)) in antennas.iter().enumerate() {
+        transport = transport
+            .expect(frame(1, 0x74, &[antenna as u8]))
+            .respond(frame(1, 0x74, &[0x10]))
+            .expect(frame(1, 0x63, &[]))
+            .respond(frame(1, 0x63, &[*threshold]))
+            .expect(frame(1, 0x7E, &[0]))
+            .respond(frame(1, 0x7E, &[*return_loss]))
+            .expect(frame(1, 0x89, &[1]))
+            .respond(frame(1, 0x89, inventory));
+    }
+    let transport = transport
+        .expect(frame(1, 0x74, &[0]))
+        .respond(frame(1, 0x74, &[0x10]));
+    let mut reader = Reader::from_transport(transport, 1, 4).unwrap();
+
+    let health = reader.antenna_health(865., -10).unwrap();
+    let statuses: Vec<_> = health.iter().map(|h| h.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            // Good return loss
+            AntennaStatus::Connected,
+            // Return loss worse than the connection detector threshold
+            AntennaStatus::Disconnected,
+            // Return loss worse than `poor_match`, with the detector disabled
+            AntennaStatus::PoorMatch,
+            // Reported missing during inventory
+            AntennaStatus::Disconnected,
+        ]
+    );
+    assert_eq!(health[1].detector_threshold, -10);
+    assert_eq!(health[1].return_loss, -5);
+}
+
+#[test]
+fn test_antenna_health_restores_work_antenna() {
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x75, &[]))
+        .respond(frame(1, 0x75, &[1]))
+        .expect(frame(1, 0x74, &[0]))
+        .respond(frame(1, 0x74, &[0x10]))
+        .expect(frame(1, 0x63, &[]))
+        .respond(frame(1, 0x63, &[0]))
+        .expect(frame(1, 0x7E, &[0]))
+        .respond(frame(1, 0x7E, &[20]))
+        .expect(frame(1, 0x89, &[1]))
+        .respond(frame(1, 0x89, &[0x11]))
+        .expect(frame(1, 0x74, &[1]))
+        .respond(frame(1, 0x74, &[0x10]));
+    let mut reader = Reader::from_transport(transport, 1, 2).unwrap();
+    assert!(reader.antenna_health(865., -10).is_err());
+}
+
+#[test]
+fn test_temporary_output_power() {
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x77, &[]))
+        .respond(frame(1, 0x77, &[30]))
+        .expect(frame(1, 0x66, &[20]))
+        .respond(frame(1, 0x66, &[0x10]))
+        .expect(frame(1, 0x66, &[30]))
+        .respond(frame(1, 0x66, &[0x10]))
+        .expect(frame(1, 0x77, &[]))
+        .respond(frame(1, 0x77, &[30, 20]));
+    let mut reader = Reader::from_transport(transport, 1, 2).unwrap();
+    assert_eq!(
+        reader.with_temporary_output_power(20, |_| Ok(1)).unwrap(),
+        1
+    );
+
+    // Differing powers can only be restored by writing to flash, so nothing is changed.
+    assert!(reader
+        .with_temporary_output_power(20, |_| -> Result<()> { panic!("Shouldn't run") })
+        .is_err());
+}
+
+#[test]
+fn test_triggered_inventory() {
+    let tag = [
+        0x14, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 100,
+    ];
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x60, &[]))
+        .respond(frame(1, 0x60, &[0, 0]))
+        .expect(frame(1, 0x60, &[]))
+        .respond(frame(1, 0x60, &[1, 0]))
+        .expect(frame(1, 0x89, &[1]))
+        .respond(frame(1, 0x89, &tag))
+        .respond(frame(1, 0x89, &[1, 0, 10, 0, 0, 0, 1]))
+        .expect(frame(1, 0x60, &[]))
+        .respond(frame(1, 0x60, &[0, 0]));
+    let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+
+    let mut events = Vec::new();
+    reader
+        .triggered_inventory(
+            GpioInput::Gpio1,
+            true,
+            Duration::from_millis(1),
+            1,
+            |event| {
+                let stop = event == TriggerEvent::Stop;
+                events.push(event);
+                !stop
+            },
+        )
+        .unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0], TriggerEvent::Start);
+    match events[1] {
+        TriggerEvent::Inventory(ref result) => assert_eq!(result.items.len(), 1),
+        ref other => panic!("Unexpected event: {:?}", other),
     }
+    assert_eq!(events[2], TriggerEvent::Stop);
 }
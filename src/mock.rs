@@ -0,0 +1,212 @@
+//! Scripted mock transport for testing without a reader
+//!
+//! A `MockTransport` is given a script of the frames the driver is expected to send and the bytes
+//! the reader should reply with. Any traffic which doesn't match the script panics, so it can be
+//! used to test code built on `Reader`:
+//!
+//! ```
+//! use invelion::mock::{frame, MockTransport};
+//! use invelion::Reader;
+//!
+//! let transport = MockTransport::new()
+//!     .expect(frame(1, 0x72, &[]))
+//!     .respond(frame(1, 0x72, &[1, 7]));
+//! let mut reader = Reader::from_transport(transport, 1, 1).unwrap();
+//! assert_eq!(reader.get_version().unwrap(), (1, 7));
+//! ```
+use std::collections::VecDeque;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::protocol::{calculate_checksum, BaudRate, START_BYTE};
+use crate::transport::Transport;
+
+#[derive(Debug)]
+enum Step {
+    Expect(Vec<u8>),
+    Respond(Vec<u8>),
+    Delay(Duration),
+    Timeout,
+}
+
+/// A transport which follows a script of requests and responses
+///
+/// Panics if the driver writes anything other than the next expected request, reads when the
+/// script is waiting for a request, or reads past the end of the script. If the transport is
+/// dropped before the script is finished, that also panics.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    script: VecDeque<Step>,
+    written: Vec<u8>,
+    timeout: Option<Duration>,
+    baud_rate: Option<BaudRate>,
+}
+
+/// Build a frame with the given reader address, command code and data
+///
+/// This works for both requests and responses. For responses which carry a status code, it
+/// should be the first byte of `data`.
+pub fn frame(address: u8, command: u8, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![START_BYTE, data.len() as u8 + 3, address, command];
+    frame.extend(data);
+    frame.push(calculate_checksum(&frame));
+    frame
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Expect the driver to send `request`
+    pub fn expect(mut self, request: Vec<u8>) -> MockTransport {
+        self.script.push_back(Step::Expect(request));
+        self
+    }
+
+    /// Reply with `response`, which is returned by subsequent reads
+    pub fn respond(mut self, response: Vec<u8>) -> MockTransport {
+        self.script.push_back(Step::Respond(response));
+        self
+    }
+
+    /// Reply with bytes which aren't a valid frame, such as line noise
+    pub fn garbage(self, bytes: &[u8]) -> MockTransport {
+        self.respond(bytes.to_vec())
+    }
+
+    /// Wait before replying
+    ///
+    /// If the delay is longer than the transport's timeout, reads will time out until it has
+    /// elapsed.
+    pub fn delay(mut self, delay: Duration) -> MockTransport {
+        self.script.push_back(Step::Delay(delay));
+        self
+    }
+
+    /// Fail the next read with a timeout, without waiting
+    pub fn timeout(mut self) -> MockTransport {
+        self.script.push_back(Step::Timeout);
+        self
+    }
+
+    /// The timeout most recently set by the driver
+    pub fn current_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// The baud rate most recently set by the driver
+    pub fn current_baud_rate(&self) -> Option<BaudRate> {
+        self.baud_rate
+    }
+
+    fn timed_out() -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, "Mock transport timed out")
+    }
+}
+
+impl io::Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.script.pop_front() {
+                Some(Step::Respond(mut response)) => {
+                    let len = buf.len().min(response.len());
+                    buf[..len].copy_from_slice(&response[..len]);
+                    response.drain(..len);
+                    if !response.is_empty() {
+                        self.script.push_front(Step::Respond(response));
+                    }
+                    return Ok(len);
+                }
+                Some(Step::Delay(delay)) => match self.timeout {
+                    Some(timeout) if delay > timeout => {
+                        thread::sleep(timeout);
+                        self.script.push_front(Step::Delay(delay - timeout));
+                        return Err(MockTransport::timed_out());
+                    }
+                    _ => thread::sleep(delay),
+                },
+                Some(Step::Timeout) => return Err(MockTransport::timed_out()),
+                Some(Step::Expect(request)) => {
+                    panic!("Read while expecting request {:02X?}", request)
+                }
+                None => panic!("Read past end of script"),
+            }
+        }
+    }
+}
+
+impl io::Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend(buf);
+        while !self.written.is_empty() {
+            let request = match self.script.front() {
+                Some(Step::Expect(request)) => request,
+                other => panic!(
+                    "Unexpected request {:02X?}, next step is {:?}",
+                    self.written, other
+                ),
+            };
+            let len = request.len().min(self.written.len());
+            if request[..len] != self.written[..len] {
+                panic!(
+                    "Unexpected request {:02X?}, expecting {:02X?}",
+                    self.written, request
+                );
+            }
+            if len < request.len() {
+                break;
+            }
+            self.written.drain(..len);
+            self.script.pop_front();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.timeout = Some(timeout);
+        Ok(())
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<()> {
+        self.baud_rate = Some(baud_rate);
+        Ok(())
+    }
+}
+
+impl Drop for MockTransport {
+    fn drop(&mut self) {
+        if !thread::panicking() && (!self.script.is_empty() || !self.written.is_empty()) {
+            panic!(
+                "Script not finished: {:?} remaining, {:02X?} written",
+                self.script, self.written
+            );
+        }
+    }
+}
+
+#[test]
+fn test_timeout() {
+    let transport = MockTransport::new()
+        .expect(frame(1, 0x72, &[]))
+        .delay(Duration::from_millis(10))
+        .timeout();
+    let mut reader = crate::Reader::from_transport(transport, 1, 1).unwrap();
+    assert!(reader.get_version().is_err());
+}
+
+#[test]
+#[should_panic(expected = "Unexpected request")]
+fn test_unexpected_request() {
+    let transport = MockTransport::new().expect(frame(1, 0x72, &[]));
+    let mut reader = crate::Reader::from_transport(transport, 1, 1).unwrap();
+    let _ = reader.get_temperature();
+}
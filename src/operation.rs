@@ -0,0 +1,876 @@
+//! Commands and the handling of their replies, independent of how they're sent
+//!
+//! Each command is described by an `Operation`, which builds the request and collects the replies
+//! into a result. `Reader` and `AsyncReader` only move frames between an operation and their
+//! transport, so both behave the same way.
+use crate::error::{Error, Result};
+use crate::iso6b::{
+    Iso6bInventoryResult, Iso6bLockStatus, Iso6bReadResult, Iso6bTag, Iso6bWriteResult, Uid,
+};
+use crate::protocol::{
+    convert_from_frequency, epc_match_data, fast_switch_inventory_data, parse_buffer_tag_count,
+    parse_epc_match, parse_gpio_level, parse_temperature, reply_error,
+    session_target_inventory_data, write_data, AntennaStatus, BaudRate, BeeperMode,
+    BufferedInventoryResult, CommandType, FastSwitchInventoryResult, GpioInput, GpioOutput,
+    InventoryItem, InventoryResult, KillPassword, KillResult, LinkProfile, LockAction, LockResult,
+    LockTarget, MemoryBank, ReadResult, ReaderIdentifier, RegionConfig, Response, ResponseCode,
+    SelectFlag, Session, Target, TriggerEvent, WriteResult, BROADCAST_ADDRESS,
+};
+
+/// How to tell which reply to a command is the last one
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Completion {
+    /// A single reply
+    Single,
+    /// One reply per tag, each carrying the number of tags, or a single "no tag" reply
+    TagResults,
+    /// Tag packets followed by a summary packet, which is shorter than any tag packet
+    Inventory,
+    /// Tag packets and antenna errors followed by a 7-byte summary packet
+    FastSwitchInventory,
+    /// 6B tag packets followed by a 2-byte summary packet, or a single "no tag" reply
+    Inventory6B,
+}
+
+/// Tracks the replies to a command to find the last one
+///
+/// This only needs the replies themselves, so it can also be used to skip the remaining replies
+/// to a command which was abandoned.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Replies {
+    pub(crate) command: CommandType,
+    completion: Completion,
+    received: usize,
+}
+
+impl Replies {
+    pub(crate) fn new(command: CommandType, completion: Completion) -> Replies {
+        Replies {
+            command,
+            completion,
+            received: 0,
+        }
+    }
+
+    /// Count a reply to the command, returning whether it's the last one
+    ///
+    /// Error codes returned by the reader are also the last reply, but they're reported as
+    /// errors rather than replies.
+    pub(crate) fn is_last(&mut self, response: &Response) -> bool {
+        self.received += 1;
+        let no_tag = response.status == Some(ResponseCode::NoTagError);
+        match self.completion {
+            Completion::Single => true,
+            Completion::TagResults => {
+                no_tag
+                    || response.status == Some(ResponseCode::BufferEmptyError)
+                    || match response.data[..] {
+                        [high, low, ..] => {
+                            self.received >= u16::from_be_bytes([high, low]) as usize
+                        }
+                        _ => true,
+                    }
+            }
+            Completion::Inventory => response.data.len() < 8,
+            Completion::FastSwitchInventory => response.data.len() == 7,
+            Completion::Inventory6B => no_tag || response.data.len() == 2,
+        }
+    }
+}
+
+/// A command to the reader, and the handling of its replies
+pub(crate) trait Operation {
+    type Output;
+
+    /// The command to send
+    fn command(&self) -> CommandType;
+
+    /// The parameters of the command
+    fn data(&self) -> &[u8];
+
+    /// How to tell which reply is the last one
+    fn completion(&self) -> Completion;
+
+    /// Handle a reply to the command
+    fn reply(&mut self, response: Response) -> Result<()>;
+
+    /// Build the result once the last reply has been handled
+    fn finish(self) -> Result<Self::Output>;
+
+    /// Track the replies to the command
+    fn replies(&self) -> Replies {
+        Replies::new(self.command(), self.completion())
+    }
+
+    /// Transform the result of the operation
+    fn map<U, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(Self::Output) -> Result<U>,
+    {
+        Map { operation: self, f }
+    }
+}
+
+/// An operation whose result is transformed by a function
+pub(crate) struct Map<O, F> {
+    operation: O,
+    f: F,
+}
+
+impl<O, U, F> Operation for Map<O, F>
+where
+    O: Operation,
+    F: FnOnce(O::Output) -> Result<U>,
+{
+    type Output = U;
+
+    fn command(&self) -> CommandType {
+        self.operation.command()
+    }
+
+    fn data(&self) -> &[u8] {
+        self.operation.data()
+    }
+
+    fn completion(&self) -> Completion {
+        self.operation.completion()
+    }
+
+    fn reply(&mut self, response: Response) -> Result<()> {
+        self.operation.reply(response)
+    }
+
+    fn finish(self) -> Result<U> {
+        (self.f)(self.operation.finish()?)
+    }
+}
+
+/// A command with a single reply
+pub(crate) struct Single {
+    command: CommandType,
+    data: Vec<u8>,
+    response: Option<Response>,
+}
+
+impl Single {
+    pub(crate) fn new(command: CommandType, data: Vec<u8>) -> Single {
+        Single {
+            command,
+            data,
+            response: None,
+        }
+    }
+}
+
+impl Operation for Single {
+    type Output = Response;
+
+    fn command(&self) -> CommandType {
+        self.command
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Single
+    }
+
+    fn reply(&mut self, response: Response) -> Result<()> {
+        self.response = Some(response);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Response> {
+        let command = self.command;
+        self.response
+            .ok_or_else(|| Error::Program(format!("No reply to {:?}", command)))
+    }
+}
+
+/// A tag access command, with one reply per tag
+pub(crate) struct TagResults<R> {
+    command: CommandType,
+    data: Vec<u8>,
+    parse: fn(&[u8]) -> Result<(usize, R)>,
+    results: Vec<R>,
+}
+
+impl<R> TagResults<R> {
+    fn new(
+        command: CommandType,
+        data: Vec<u8>,
+        parse: fn(&[u8]) -> Result<(usize, R)>,
+    ) -> TagResults<R> {
+        TagResults {
+            command,
+            data,
+            parse,
+            results: Vec::new(),
+        }
+    }
+}
+
+impl<R> Operation for TagResults<R> {
+    type Output = Vec<R>;
+
+    fn command(&self) -> CommandType {
+        self.command
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::TagResults
+    }
+
+    fn reply(&mut self, response: Response) -> Result<()> {
+        if response.status == Some(ResponseCode::NoTagError)
+            || response.status == Some(ResponseCode::BufferEmptyError)
+        {
+            // No tags found
+            return Ok(());
+        }
+        let (_, result) = (self.parse)(&response.data)?;
+        self.results.push(result);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<R>> {
+        Ok(self.results)
+    }
+}
+
+/// A real-time inventory, with one reply per tag followed by a summary
+pub(crate) struct Inventory {
+    command: CommandType,
+    data: Vec<u8>,
+    parse: fn(&[u8]) -> Result<InventoryItem>,
+    fast_tid: bool,
+    items: Vec<InventoryItem>,
+    summary: Vec<u8>,
+}
+
+impl Inventory {
+    fn new(
+        command: CommandType,
+        data: Vec<u8>,
+        parse: fn(&[u8]) -> Result<InventoryItem>,
+        fast_tid: bool,
+    ) -> Inventory {
+        Inventory {
+            command,
+            data,
+            parse,
+            fast_tid,
+            items: Vec::new(),
+            summary: Vec::new(),
+        }
+    }
+
+    /// Take the tags received so far
+    #[cfg(feature = "async")]
+    pub(crate) fn take_items(&mut self) -> Vec<InventoryItem> {
+        std::mem::take(&mut self.items)
+    }
+}
+
+impl Operation for Inventory {
+    type Output = InventoryResult;
+
+    fn command(&self) -> CommandType {
+        self.command
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Inventory
+    }
+
+    fn reply(&mut self, response: Response) -> Result<()> {
+        if response.data.len() < 8 {
+            self.summary = response.data;
+            return Ok(());
+        }
+        let mut item = (self.parse)(&response.data)?;
+        if self.fast_tid {
+            item.split_fast_tid();
+        }
+        self.items.push(item);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<InventoryResult> {
+        InventoryResult::from_bytes(&self.summary, self.items)
+    }
+}
+
+/// An inventory which switches between antennas on the reader
+pub(crate) struct FastSwitchInventory {
+    data: Vec<u8>,
+    fast_tid: bool,
+    result: FastSwitchInventoryResult,
+}
+
+impl Operation for FastSwitchInventory {
+    type Output = FastSwitchInventoryResult;
+
+    fn command(&self) -> CommandType {
+        CommandType::FastSwitchAntInventory
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::FastSwitchInventory
+    }
+
+    fn reply(&mut self, response: Response) -> Result<()> {
+        self.result.add_packet(&response.data)?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<FastSwitchInventoryResult> {
+        if self.fast_tid {
+            self.result
+                .antennas
+                .iter_mut()
+                .flat_map(|a| a.items.iter_mut())
+                .for_each(InventoryItem::split_fast_tid);
+        }
+        Ok(self.result)
+    }
+}
+
+/// A 6B inventory, with one reply per tag followed by a summary
+pub(crate) struct Inventory6B {
+    antenna: u8,
+    tags: Vec<Iso6bTag>,
+}
+
+impl Operation for Inventory6B {
+    type Output = Iso6bInventoryResult;
+
+    fn command(&self) -> CommandType {
+        CommandType::Inventory6B
+    }
+
+    fn data(&self) -> &[u8] {
+        &[]
+    }
+
+    fn completion(&self) -> Completion {
+        Completion::Inventory6B
+    }
+
+    fn reply(&mut self, response: Response) -> Result<()> {
+        if response.status == Some(ResponseCode::NoTagError) {
+            return Ok(());
+        }
+        // The final packet contains the antenna and the number of tags found
+        if response.data.len() == 2 {
+            self.antenna = response.data[0];
+        } else {
+            self.tags.push(Iso6bTag::from_bytes(&response.data)?);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Iso6bInventoryResult> {
+        Ok(Iso6bInventoryResult {
+            antenna: self.antenna,
+            tags: self.tags,
+        })
+    }
+}
+
+/// A command which only returns a status code
+fn set(command: CommandType, data: Vec<u8>) -> impl Operation<Output = ()> {
+    Single::new(command, data).map(|_| Ok(()))
+}
+
+/// A command with no parameters and a single reply
+pub(crate) fn simple(command: CommandType) -> Single {
+    Single::new(command, vec![])
+}
+
+/// The first byte of a reply which should have one
+fn first_byte(response: Response) -> Result<u8> {
+    match response.data.first() {
+        Some(byte) => Ok(*byte),
+        None => Err(reply_error(&response.data)),
+    }
+}
+
+/// Combine the result of an action with the result of cleaning up after it
+///
+/// If both fail, the error from the action is returned.
+pub(crate) fn with_cleanup<R>(result: Result<R>, cleanup: Result<()>) -> Result<R> {
+    match (result, cleanup) {
+        (Ok(value), Ok(())) => Ok(value),
+        (Ok(_), Err(e)) => Err(e),
+        (Err(e), _) => Err(e),
+    }
+}
+
+/// Whether the reader acknowledged a command which not all firmware replies to
+pub(crate) fn acknowledged<R>(result: Result<R>) -> Result<bool> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(Error::Io(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Classify an antenna from its return loss and the result of an inventory on it
+pub(crate) fn antenna_status(
+    inventory: Result<InventoryResult>,
+    return_loss: i8,
+    detector_threshold: i8,
+    poor_match: i8,
+) -> Result<AntennaStatus> {
+    match inventory {
+        Err(Error::Protocol(ResponseCode::AntennaMissingError)) => Ok(AntennaStatus::Disconnected),
+        Err(e) => Err(e),
+        Ok(_) => {
+            if detector_threshold != 0
+                && return_loss.unsigned_abs() < detector_threshold.unsigned_abs()
+            {
+                Ok(AntennaStatus::Disconnected)
+            } else if return_loss.unsigned_abs() < poor_match.unsigned_abs() {
+                Ok(AntennaStatus::PoorMatch)
+            } else {
+                Ok(AntennaStatus::Connected)
+            }
+        }
+    }
+}
+
+/// The power to restore after a temporary output power change
+///
+/// The temporary setting applies to all antennas, so it can only restore a single power.
+pub(crate) fn restorable_output_power(power: &[u8]) -> Result<u8> {
+    match power.split_first() {
+        Some((first, rest)) if rest.iter().all(|p| p == first) => Ok(*first),
+        _ => Err(Error::Program(format!(
+            "Output powers {:?} can't be restored with a temporary setting",
+            power
+        ))),
+    }
+}
+
+/// The event to report when a trigger input is read, if the trigger has changed
+pub(crate) fn trigger_change(triggered: &mut bool, level: bool) -> Option<TriggerEvent> {
+    if level == *triggered {
+        return None;
+    }
+    *triggered = level;
+    if level {
+        Some(TriggerEvent::Start)
+    } else {
+        Some(TriggerEvent::Stop)
+    }
+}
+
+pub(crate) fn reset() -> impl Operation<Output = ()> {
+    set(CommandType::Reset, vec![])
+}
+
+pub(crate) fn set_baud_rate(baud_rate: BaudRate) -> impl Operation<Output = ()> {
+    set(CommandType::SetUARTBaudRate, vec![baud_rate as u8])
+}
+
+pub(crate) fn set_reader_address(address: u8) -> Result<impl Operation<Output = ()>> {
+    if address == BROADCAST_ADDRESS {
+        return Err(Error::Protocol(ResponseCode::InvalidReaderAddressError));
+    }
+    Ok(set(CommandType::SetReaderAddress, vec![address]))
+}
+
+pub(crate) fn get_version() -> impl Operation<Output = (u8, u8)> {
+    simple(CommandType::GetFirmwareVersion).map(|response| match response.data[..] {
+        [major, minor] => Ok((major, minor)),
+        _ => Err(reply_error(&response.data)),
+    })
+}
+
+pub(crate) fn set_reader_identifier(identifier: &ReaderIdentifier) -> impl Operation<Output = ()> {
+    set(
+        CommandType::SetReaderIdentifier,
+        identifier.as_bytes().to_vec(),
+    )
+}
+
+pub(crate) fn get_reader_identifier() -> impl Operation<Output = ReaderIdentifier> {
+    simple(CommandType::GetReaderIdentifier)
+        .map(|response| ReaderIdentifier::from_bytes(&response.data))
+}
+
+pub(crate) fn set_link_profile(profile: LinkProfile) -> impl Operation<Output = ()> {
+    set(CommandType::SetRFLinkProfile, vec![profile as u8])
+}
+
+pub(crate) fn get_link_profile() -> impl Operation<Output = LinkProfile> {
+    simple(CommandType::GetRFLinkProfile).map(|response| LinkProfile::from_response(&response.data))
+}
+
+pub(crate) fn set_work_antenna(antenna_id: u8) -> impl Operation<Output = ()> {
+    set(CommandType::SetWorkAntenna, vec![antenna_id])
+}
+
+pub(crate) fn get_work_antenna() -> impl Operation<Output = u8> {
+    simple(CommandType::GetWorkAntenna).map(first_byte)
+}
+
+pub(crate) fn get_antenna_connection_detector() -> impl Operation<Output = i8> {
+    simple(CommandType::GetAntConnectionDetector).map(|response| Ok(-(first_byte(response)? as i8)))
+}
+
+pub(crate) fn set_antenna_connection_detector(threshold: i8) -> impl Operation<Output = ()> {
+    set(
+        CommandType::SetAntConnectionDetector,
+        vec![threshold.unsigned_abs()],
+    )
+}
+
+pub(crate) fn set_output_power(
+    power: &[u8],
+    antenna_count: usize,
+) -> Result<impl Operation<Output = ()>> {
+    if power.len() != antenna_count {
+        return Err(Error::Program(format!(
+            "Expected output power for {} antennas, got {}",
+            antenna_count,
+            power.len()
+        )));
+    }
+    Ok(set(CommandType::SetOutputPower, power.to_vec()))
+}
+
+pub(crate) fn set_temporary_output_power(power: u8) -> impl Operation<Output = ()> {
+    set(CommandType::SetTemporaryOutputPower, vec![power])
+}
+
+pub(crate) fn get_output_power(antenna_count: usize) -> impl Operation<Output = Vec<u8>> {
+    simple(CommandType::GetOutputPower).map(move |response| {
+        if response.data.len() == 1 {
+            // Reader only sends the power once if all antennas are set the same,
+            // so repeat it for consistency.
+            return Ok(vec![response.data[0]; antenna_count]);
+        }
+        Ok(response.data)
+    })
+}
+
+pub(crate) fn get_frequency_region() -> impl Operation<Output = RegionConfig> {
+    simple(CommandType::GetFrequencyRegion).map(|response| RegionConfig::from_bytes(&response.data))
+}
+
+pub(crate) fn set_frequency_region(config: RegionConfig) -> Result<impl Operation<Output = ()>> {
+    config.validate()?;
+    Ok(set(CommandType::SetFrequencyRegion, config.to_bytes()))
+}
+
+pub(crate) fn read_gpio(input: GpioInput) -> impl Operation<Output = bool> {
+    simple(CommandType::ReadGPIOValue).map(move |response| parse_gpio_level(&response.data, input))
+}
+
+pub(crate) fn write_gpio(output: GpioOutput, value: bool) -> impl Operation<Output = ()> {
+    set(CommandType::WriteGPIOValue, vec![output as u8, value as u8])
+}
+
+pub(crate) fn set_beeper_mode(mode: BeeperMode) -> impl Operation<Output = ()> {
+    set(CommandType::SetBeeperMode, vec![mode as u8])
+}
+
+pub(crate) fn get_temperature() -> impl Operation<Output = i8> {
+    simple(CommandType::GetReaderTemperature).map(|response| parse_temperature(&response.data))
+}
+
+pub(crate) fn measure_return_loss(frequency: f32) -> Result<impl Operation<Output = i8>> {
+    Ok(Single::new(
+        CommandType::GetRFPortReturnLoss,
+        vec![convert_from_frequency(frequency)?],
+    )
+    .map(|response| Ok(-(first_byte(response)? as i8))))
+}
+
+pub(crate) fn set_fast_tid(enabled: bool, save: bool) -> impl Operation<Output = ()> {
+    let command = if save {
+        CommandType::SetAndSaveImpinjFastTIC
+    } else {
+        CommandType::SetImpinjFastTID
+    };
+    set(command, vec![if enabled { 0x8D } else { 0x00 }])
+}
+
+pub(crate) fn get_fast_tid() -> impl Operation<Output = bool> {
+    simple(CommandType::GetImpinjFastTID).map(|response| Ok(first_byte(response)? == 0x8D))
+}
+
+pub(crate) fn real_time_inventory(repeat: u8, fast_tid: bool) -> Inventory {
+    Inventory::new(
+        CommandType::RealTimeInventory,
+        vec![repeat],
+        InventoryItem::from_bytes,
+        fast_tid,
+    )
+}
+
+pub(crate) fn session_target_inventory(
+    session: Session,
+    target: Target,
+    select: Option<SelectFlag>,
+    phase: bool,
+    repeat: u8,
+    fast_tid: bool,
+) -> Inventory {
+    Inventory::new(
+        CommandType::CustomizedSessionTargetInventory,
+        session_target_inventory_data(session, target, select, phase, repeat),
+        if phase {
+            InventoryItem::from_bytes_with_phase
+        } else {
+            InventoryItem::from_bytes
+        },
+        fast_tid,
+    )
+}
+
+pub(crate) fn fast_switch_inventory(
+    sequence: &[(u8, u8)],
+    antenna_count: usize,
+    interval: u8,
+    repeat: u8,
+    fast_tid: bool,
+) -> Result<FastSwitchInventory> {
+    Ok(FastSwitchInventory {
+        data: fast_switch_inventory_data(sequence, antenna_count, interval, repeat)?,
+        fast_tid,
+        result: FastSwitchInventoryResult::new(sequence),
+    })
+}
+
+pub(crate) fn buffered_inventory(repeat: u8) -> impl Operation<Output = BufferedInventoryResult> {
+    Single::new(CommandType::Inventory, vec![repeat]).map(|response| {
+        if response.status == Some(ResponseCode::NoTagError) {
+            return Err(Error::from(ResponseCode::NoTagError));
+        }
+        BufferedInventoryResult::from_bytes(&response.data)
+    })
+}
+
+pub(crate) fn get_buffer_tag_count() -> impl Operation<Output = u16> {
+    simple(CommandType::GetBufferTagCount).map(|response| parse_buffer_tag_count(&response.data))
+}
+
+/// Fetch the tags in the inventory buffer with `GetInventoryBuffer` or
+/// `GetAndResetInventoryBuffer`
+pub(crate) fn inventory_buffer(
+    command: CommandType,
+    fast_tid: bool,
+) -> impl Operation<Output = Vec<InventoryItem>> {
+    TagResults::new(command, vec![], InventoryItem::from_buffer_bytes).map(move |mut items| {
+        if fast_tid {
+            items.iter_mut().for_each(InventoryItem::split_fast_tid);
+        }
+        Ok(items)
+    })
+}
+
+pub(crate) fn reset_inventory_buffer() -> impl Operation<Output = ()> {
+    set(CommandType::ResetInventoryBuffer, vec![])
+}
+
+pub(crate) fn read(
+    bank: MemoryBank,
+    password: &[u8],
+    start: u8,
+    length: u8,
+) -> TagResults<ReadResult> {
+    let mut data = vec![bank as u8, start, length];
+    data.extend(password);
+    TagResults::new(CommandType::Read, data, ReadResult::from_bytes)
+}
+
+pub(crate) fn write(
+    bank: MemoryBank,
+    password: &[u8],
+    start_word: u8,
+    data: &[u8],
+) -> Result<TagResults<WriteResult>> {
+    Ok(TagResults::new(
+        CommandType::Write,
+        write_data(bank, password, start_word, data)?,
+        WriteResult::from_bytes,
+    ))
+}
+
+pub(crate) fn lock(
+    target: LockTarget,
+    action: LockAction,
+    password: &[u8],
+) -> TagResults<LockResult> {
+    let mut data = password.to_vec();
+    data.extend(&[target as u8, action as u8]);
+    TagResults::new(CommandType::Lock, data, LockResult::from_bytes)
+}
+
+/// Build a kill command, given the EPC access match currently set on the reader
+pub(crate) fn kill(
+    password: KillPassword,
+    epc_match: Option<Vec<u8>>,
+) -> Result<TagResults<KillResult>> {
+    if epc_match.is_none() {
+        return Err(Error::Program(
+            "Refusing to kill tags without an EPC access match set".to_string(),
+        ));
+    }
+    Ok(TagResults::new(
+        CommandType::Kill,
+        password.as_bytes().to_vec(),
+        KillResult::from_bytes,
+    ))
+}
+
+pub(crate) fn set_epc_match(epc: &[u8]) -> impl Operation<Output = ()> {
+    set(CommandType::SetAccessEPCMatch, epc_match_data(epc))
+}
+
+/// Set an EPC access match which will be cleared afterwards
+pub(crate) fn set_scoped_epc_match(epc: &[u8]) -> Result<impl Operation<Output = ()>> {
+    if epc.is_empty() {
+        return Err(Error::Program("EPC match must not be empty".to_string()));
+    }
+    Ok(set_epc_match(epc))
+}
+
+pub(crate) fn get_epc_match() -> impl Operation<Output = Option<Vec<u8>>> {
+    simple(CommandType::GetAccessEPCMatch).map(|response| parse_epc_match(&response.data))
+}
+
+pub(crate) fn inventory_6b() -> Inventory6B {
+    Inventory6B {
+        antenna: 0,
+        tags: Vec::new(),
+    }
+}
+
+/// Check a 6B access response, treating "no tag" as an error
+fn access_response(response: Response) -> Result<Vec<u8>> {
+    if response.status == Some(ResponseCode::NoTagError) {
+        return Err(Error::from(ResponseCode::NoTagError));
+    }
+    if response.data.is_empty() {
+        return Err(Error::Program(format!(
+            "Empty response to {:?}",
+            response.command
+        )));
+    }
+    Ok(response.data)
+}
+
+pub(crate) fn read_6b(
+    uid: &Uid,
+    start: u8,
+    length: u8,
+) -> impl Operation<Output = Iso6bReadResult> {
+    let mut data = uid.to_vec();
+    data.extend(&[start, length]);
+    Single::new(CommandType::Read6B, data).map(|response| {
+        let data = access_response(response)?;
+        Ok(Iso6bReadResult {
+            antenna: data[0],
+            data: data[1..].to_vec(),
+        })
+    })
+}
+
+pub(crate) fn write_6b(
+    uid: &Uid,
+    start: u8,
+    data: &[u8],
+) -> Result<impl Operation<Output = Iso6bWriteResult>> {
+    if data.is_empty() || data.len() > 255 {
+        return Err(Error::Program(format!(
+            "6B write data must be between 1 and 255 bytes, got {}",
+            data.len()
+        )));
+    }
+    let mut cmd_data = uid.to_vec();
+    cmd_data.extend(&[start, data.len() as u8]);
+    cmd_data.extend(data);
+    Ok(Single::new(CommandType::Write6B, cmd_data).map(|response| {
+        let data = access_response(response)?;
+        Ok(Iso6bWriteResult {
+            antenna: data[0],
+            written: *data.get(1).unwrap_or(&0),
+        })
+    }))
+}
+
+pub(crate) fn lock_6b(uid: &Uid, address: u8) -> impl Operation<Output = Iso6bLockStatus> {
+    let mut data = uid.to_vec();
+    data.push(address);
+    Single::new(CommandType::Lock6B, data).map(|response| {
+        let data = access_response(response)?;
+        match data.get(1) {
+            Some(0x00) => Ok(Iso6bLockStatus::Locked),
+            Some(0xFE) => Ok(Iso6bLockStatus::AlreadyLocked),
+            Some(0xFF) => Ok(Iso6bLockStatus::Failed),
+            other => Err(Error::Program(format!(
+                "Invalid 6B lock status: {:?}",
+                other
+            ))),
+        }
+    })
+}
+
+pub(crate) fn query_lock_6b(uid: &Uid, address: u8) -> impl Operation<Output = bool> {
+    let mut data = uid.to_vec();
+    data.push(address);
+    Single::new(CommandType::QueryLock6B, data).map(|response| {
+        let data = access_response(response)?;
+        match data.get(1) {
+            Some(0x00) => Ok(false),
+            Some(0xFE) => Ok(true),
+            other => Err(Error::Program(format!(
+                "Invalid 6B lock query status: {:?}",
+                other
+            ))),
+        }
+    })
+}
+
+#[test]
+fn test_replies() {
+    let response = |command, status, data: &[u8]| Response {
+        address: 1,
+        command,
+        status,
+        data: data.to_vec(),
+    };
+
+    let mut replies = Replies::new(CommandType::Read, Completion::TagResults);
+    assert!(!replies.is_last(&response(CommandType::Read, None, &[0, 2, 9])));
+    assert!(replies.is_last(&response(CommandType::Read, None, &[0, 2, 9])));
+    let mut replies = Replies::new(CommandType::Read, Completion::TagResults);
+    assert!(replies.is_last(&response(
+        CommandType::Read,
+        Some(ResponseCode::NoTagError),
+        &[]
+    )));
+
+    let mut replies = Replies::new(CommandType::RealTimeInventory, Completion::Inventory);
+    assert!(!replies.is_last(&response(CommandType::RealTimeInventory, None, &[0; 12])));
+    assert!(replies.is_last(&response(CommandType::RealTimeInventory, None, &[0; 7])));
+}
@@ -1,10 +1,15 @@
 use bitreader::BitReader;
 use num_enum::TryFromPrimitive;
-use std::convert::TryFrom;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::time::Duration;
 
 use crate::error::{Error, Result};
 
 pub(crate) const START_BYTE: u8 = 0xA0;
+pub(crate) const BROADCAST_ADDRESS: u8 = 0xFF;
 
 #[derive(Copy, Clone, PartialEq, Debug, TryFromPrimitive)]
 #[repr(u8)]
@@ -62,7 +67,7 @@ pub(crate) enum CommandType {
     ResetInventoryBuffer = 0x93,
 }
 
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
 #[repr(u8)]
 pub enum ResponseCode {
     Success = 0x10,
@@ -120,16 +125,183 @@ fn command_has_response_code(command: CommandType, length: usize) -> bool {
         | CommandType::GetReaderTemperature
         | CommandType::GetRFPortReturnLoss
         | CommandType::GetWorkAntenna
-        | CommandType::GetAntConnectionDetector => false,
-        CommandType::RealTimeInventory | CommandType::Read | CommandType::SetAccessEPCMatch => {
-            length == 0x04
-        }
+        | CommandType::GetAntConnectionDetector
+        | CommandType::GetAccessEPCMatch
+        | CommandType::GetBufferTagCount
+        | CommandType::GetImpinjFastTID
+        | CommandType::GetFrequencyRegion
+        | CommandType::ReadGPIOValue
+        | CommandType::GetReaderIdentifier
+        | CommandType::GetRFLinkProfile => false,
+        CommandType::RealTimeInventory
+        | CommandType::CustomizedSessionTargetInventory
+        | CommandType::FastSwitchAntInventory
+        | CommandType::Inventory
+        | CommandType::GetInventoryBuffer
+        | CommandType::GetAndResetInventoryBuffer
+        | CommandType::Read
+        | CommandType::Write
+        | CommandType::Lock
+        | CommandType::Kill
+        | CommandType::Inventory6B
+        | CommandType::Read6B
+        | CommandType::Write6B
+        | CommandType::Lock6B
+        | CommandType::QueryLock6B => length == 0x04,
         _ => true,
     }
 }
 
+/// Serial baud rates supported by the reader
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum BaudRate {
+    Baud38400 = 0x03,
+    Baud115200 = 0x04,
+}
+
+/// When the reader's beeper sounds
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum BeeperMode {
+    Quiet = 0x00,
+    /// Beep after each inventory round in which a tag was read
+    BeepAfterRound = 0x01,
+    /// Beep for every tag read
+    BeepAfterTag = 0x02,
+}
+
+/// GPIO input pins
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GpioInput {
+    Gpio1,
+    Gpio2,
+}
+
+/// GPIO output pins
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum GpioOutput {
+    Gpio3 = 0x03,
+    Gpio4 = 0x04,
+}
+
+/// A 12-byte identifier stored in the reader's flash
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReaderIdentifier(pub [u8; 12]);
+
+impl ReaderIdentifier {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<ReaderIdentifier> {
+        let bytes = data
+            .try_into()
+            .map_err(|_| Error::Program(format!("Invalid reader identifier: {:?}", data)))?;
+        Ok(ReaderIdentifier(bytes))
+    }
+}
+
+impl fmt::Display for ReaderIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reader to tag modulation
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Modulation {
+    /// Double-sideband amplitude shift keying
+    DSBASK,
+    /// Phase-reversal amplitude shift keying
+    PRASK,
+}
+
+/// Tag to reader data encoding
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    FM0,
+    Miller2,
+    Miller4,
+    Miller8,
+}
+
+/// Gen2 RF link profiles
+///
+/// Profiles with a higher backscatter link frequency read tags faster, while slower profiles are
+/// more robust in dense reader environments.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum LinkProfile {
+    Profile0 = 0xD0,
+    /// The default profile
+    Profile1 = 0xD1,
+    Profile2 = 0xD2,
+    Profile3 = 0xD3,
+}
+
+impl LinkProfile {
+    pub fn modulation(self) -> Modulation {
+        match self {
+            LinkProfile::Profile0 | LinkProfile::Profile3 => Modulation::DSBASK,
+            LinkProfile::Profile1 | LinkProfile::Profile2 => Modulation::PRASK,
+        }
+    }
+
+    /// Reader to tag data reference interval (Tari) in microseconds
+    pub fn tari(self) -> f32 {
+        match self {
+            LinkProfile::Profile3 => 6.25,
+            _ => 25.,
+        }
+    }
+
+    /// Backscatter link frequency in kHz
+    pub fn blf(self) -> u32 {
+        match self {
+            LinkProfile::Profile0 => 40,
+            LinkProfile::Profile1 => 250,
+            LinkProfile::Profile2 => 300,
+            LinkProfile::Profile3 => 400,
+        }
+    }
+
+    pub fn encoding(self) -> Encoding {
+        match self {
+            LinkProfile::Profile0 | LinkProfile::Profile3 => Encoding::FM0,
+            LinkProfile::Profile1 | LinkProfile::Profile2 => Encoding::Miller4,
+        }
+    }
+
+    /// Parse the reply to a get link profile command
+    ///
+    /// This reply doesn't have a response code, but profile IDs don't overlap with response
+    /// codes so errors can still be detected.
+    pub(crate) fn from_response(data: &[u8]) -> Result<LinkProfile> {
+        let byte = match data.first() {
+            Some(byte) => *byte,
+            None => return Err(reply_error(data)),
+        };
+        if let Ok(profile) = LinkProfile::try_from(byte) {
+            return Ok(profile);
+        }
+        match ResponseCode::try_from(byte) {
+            Ok(code) => Err(Error::from(code)),
+            Err(_) => Err(Error::Program(format!("Invalid link profile: {:?}", byte))),
+        }
+    }
+}
+
 /// Enum of frequency regions
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum FrequencyRegion {
     FCC = 0x01,
@@ -138,8 +310,200 @@ pub enum FrequencyRegion {
     UserDefined = 0x04,
 }
 
+impl FrequencyRegion {
+    /// The range of channel numbers which can be used in this region
+    ///
+    /// Channel numbers map to frequencies as in table 4 in the datasheet. User-defined
+    /// regions don't use channel numbers.
+    pub fn channels(self) -> Option<(u8, u8)> {
+        match self {
+            FrequencyRegion::FCC => Some((7, 59)),
+            FrequencyRegion::ETSI => Some((0, 6)),
+            FrequencyRegion::CHN => Some((43, 53)),
+            FrequencyRegion::UserDefined => None,
+        }
+    }
+}
+
+/// Frequency region configuration
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RegionConfig {
+    /// A standard region, hopping between the given channel numbers (inclusive)
+    Standard {
+        region: FrequencyRegion,
+        start_channel: u8,
+        end_channel: u8,
+    },
+    /// A user-defined hop table
+    UserDefined {
+        /// Start frequency in kHz
+        start_frequency: u32,
+        /// Channel spacing in kHz (must be a multiple of 10 kHz)
+        channel_spacing: u32,
+        /// Number of channels
+        channel_count: u8,
+    },
+}
+
+impl RegionConfig {
+    /// Check the configuration is valid before sending it to the reader
+    ///
+    /// This returns the same errors as the reader would.
+    pub(crate) fn validate(self) -> Result<()> {
+        match self {
+            RegionConfig::Standard {
+                region,
+                start_channel,
+                end_channel,
+            } => {
+                let (min, max) = region
+                    .channels()
+                    .ok_or(Error::Protocol(ResponseCode::InvalidFrequencyRegionError))?;
+                if start_channel < min || end_channel > max || start_channel > end_channel {
+                    return Err(Error::Protocol(ResponseCode::InvalidFrequencyRangeError));
+                }
+            }
+            RegionConfig::UserDefined {
+                start_frequency,
+                channel_spacing,
+                channel_count,
+            } => {
+                let end_frequency = u64::from(start_frequency)
+                    + u64::from(channel_spacing) * u64::from(channel_count.saturating_sub(1));
+                if channel_count == 0
+                    || channel_spacing % 10 != 0
+                    || channel_spacing / 10 > 255
+                    || start_frequency < 840_000
+                    || end_frequency > 960_000
+                {
+                    return Err(Error::Protocol(ResponseCode::InvalidFrequencyRangeError));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_bytes(self) -> Vec<u8> {
+        match self {
+            RegionConfig::Standard {
+                region,
+                start_channel,
+                end_channel,
+            } => vec![region as u8, start_channel, end_channel],
+            RegionConfig::UserDefined {
+                start_frequency,
+                channel_spacing,
+                channel_count,
+            } => {
+                let mut data = vec![
+                    FrequencyRegion::UserDefined as u8,
+                    (channel_spacing / 10) as u8,
+                    channel_count,
+                ];
+                data.extend(&start_frequency.to_be_bytes()[1..]);
+                data
+            }
+        }
+    }
+
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<RegionConfig> {
+        let mut reader = BitReader::new(data);
+        let region = FrequencyRegion::try_from(reader.read_u8(8)?)?;
+        if region == FrequencyRegion::UserDefined {
+            Ok(RegionConfig::UserDefined {
+                channel_spacing: u32::from(reader.read_u8(8)?) * 10,
+                channel_count: reader.read_u8(8)?,
+                start_frequency: reader.read_u32(24)?,
+            })
+        } else {
+            Ok(RegionConfig::Standard {
+                region,
+                start_channel: reader.read_u8(8)?,
+                end_channel: reader.read_u8(8)?,
+            })
+        }
+    }
+}
+
+/// Gen2 inventory session
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Session {
+    S0 = 0x00,
+    S1 = 0x01,
+    S2 = 0x02,
+    S3 = 0x03,
+}
+
+/// Gen2 inventoried flag to target
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Target {
+    A = 0x00,
+    B = 0x01,
+}
+
+/// Which tags to inventory, based on their SL flag
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum SelectFlag {
+    All = 0x00,
+    NotSelected = 0x02,
+    Selected = 0x03,
+}
+
+/// Build the data for a customised session/target inventory command
+pub(crate) fn session_target_inventory_data(
+    session: Session,
+    target: Target,
+    select: Option<SelectFlag>,
+    phase: bool,
+    repeat: u8,
+) -> Vec<u8> {
+    let mut data = vec![session as u8, target as u8];
+    if select.is_some() || phase {
+        data.push(select.unwrap_or(SelectFlag::All) as u8);
+    }
+    if phase {
+        data.push(0x01);
+    }
+    data.push(repeat);
+    data
+}
+
+/// Build the data for a fast antenna-switching inventory command
+pub(crate) fn fast_switch_inventory_data(
+    sequence: &[(u8, u8)],
+    antenna_count: usize,
+    interval: u8,
+    repeat: u8,
+) -> Result<Vec<u8>> {
+    if sequence.is_empty() || sequence.len() > 4 {
+        return Err(Error::Program(format!(
+            "Antenna sequence must contain between 1 and 4 antennas, got {}",
+            sequence.len()
+        )));
+    }
+    if let Some((antenna, _)) = sequence
+        .iter()
+        .find(|(antenna, _)| *antenna as usize >= antenna_count)
+    {
+        return Err(Error::Program(format!("Invalid antenna ID {}", antenna)));
+    }
+
+    let mut data = Vec::new();
+    for i in 0..4 {
+        // Antenna IDs above 3 tell the reader to skip this slot
+        let (antenna, stay) = sequence.get(i).cloned().unwrap_or((0xFF, 0));
+        data.extend(&[antenna, stay]);
+    }
+    data.extend(&[interval, repeat]);
+    Ok(data)
+}
+
 /// Enum of memory banks
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
 #[repr(u8)]
 pub enum MemoryBank {
     Reserved = 0x00,
@@ -163,9 +527,9 @@ fn convert_to_frequency(freq: u8) -> f32 {
 ///
 /// This is derived from table 4 in the datasheet.
 pub(crate) fn convert_from_frequency(frequency: f32) -> Result<u8> {
-    if frequency >= 865. && frequency <= 868. {
+    if (865. ..=868.).contains(&frequency) {
         return Ok(((frequency - 865.) / 0.5) as u8);
-    } else if frequency >= 902. && frequency <= 928. {
+    } else if (902. ..=928.).contains(&frequency) {
         return Ok(((frequency - 902.) / 0.5) as u8 + 7);
     }
     Err(Error::Program(format!("Invalid frequency {}", frequency)))
@@ -183,10 +547,82 @@ fn convert_rssi(rssi: u8) -> i8 {
     }
 }
 
+/// Convert the reply to a temperature request to celsius
+pub(crate) fn parse_temperature(data: &[u8]) -> Result<i8> {
+    match data {
+        // Datasheet says the first byte is 0x01 if negative, but this doesn't
+        // seem to be correct. Guessing they got that reversed. It's not that cold in here.
+        [0x00, temp] => Ok(-(*temp as i8)),
+        [_, temp] => Ok(*temp as i8),
+        _ => Err(reply_error(data)),
+    }
+}
+
+/// Build the data to set the EPC access match, or to clear it if `epc` is empty
+pub(crate) fn epc_match_data(epc: &[u8]) -> Vec<u8> {
+    if epc.is_empty() {
+        vec![0x01] // Clear match
+    } else {
+        let mut data = vec![0x00, epc.len() as u8];
+        data.extend(epc);
+        data
+    }
+}
+
+/// Parse the EPC access match, returning `None` if no match is set
+pub(crate) fn parse_epc_match(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    match data.first() {
+        Some(0x00) => match (data.get(1), data.get(2..)) {
+            (Some(&len), Some(epc)) if epc.len() >= len as usize => {
+                Ok(Some(epc[..len as usize].to_vec()))
+            }
+            _ => Err(Error::Program(format!(
+                "Short EPC match response: {:?}",
+                data
+            ))),
+        },
+        Some(0x01) => Ok(None),
+        _ => Err(Error::Program(format!(
+            "Unexpected EPC match response: {:?}",
+            data
+        ))),
+    }
+}
+
+/// Build the error for a reply which doesn't have the expected length
+///
+/// Replies to commands without a response code are a single status byte on failure.
+pub(crate) fn reply_error(data: &[u8]) -> Error {
+    match data {
+        [code] => match ResponseCode::try_from(*code) {
+            Ok(code) => Error::from(code),
+            Err(e) => Error::from(e),
+        },
+        _ => Error::Program(format!("Unexpected reply length: {:?}", data)),
+    }
+}
+
+/// Parse the number of unique tags in the inventory buffer
+pub(crate) fn parse_buffer_tag_count(data: &[u8]) -> Result<u16> {
+    match data {
+        [high, low] => Ok(u16::from_be_bytes([*high, *low])),
+        _ => Err(reply_error(data)),
+    }
+}
+
+/// Parse the level of a GPIO input from the read GPIO reply
+pub(crate) fn parse_gpio_level(data: &[u8], input: GpioInput) -> Result<bool> {
+    match (data, input) {
+        ([gpio1, _], GpioInput::Gpio1) => Ok(*gpio1 != 0x00),
+        ([_, gpio2], GpioInput::Gpio2) => Ok(*gpio2 != 0x00),
+        _ => Err(reply_error(data)),
+    }
+}
+
 /// Calculate checksum digit
 ///
 /// Datasheet section 6
-fn calculate_checksum(data: &[u8]) -> u8 {
+pub(crate) fn calculate_checksum(data: &[u8]) -> u8 {
     let mut sum: u8 = 0;
 
     for byte in data {
@@ -229,29 +665,38 @@ pub(crate) struct Response {
 
 impl Response {
     pub(crate) fn from_bytes(data: &[u8]) -> Result<Response> {
-        assert_eq!(data[0], START_BYTE);
-        assert_eq!(data[1] as usize, data.len() - 2);
         let len = data.len();
+        // Start byte, length, address, command and checksum
+        if len < 5 || data[0] != START_BYTE || data[1] as usize != len - 2 {
+            return Err(Error::Program(format!("Malformed packet: {:?}", data)));
+        }
 
         let checksum = calculate_checksum(&data[0..len - 1]);
         if data[len - 1] != checksum {
             return Err(Error::Program(format!(
                 "Bad checksum: got {:?}, expecting {:?}",
-                data[len], checksum
+                data[len - 1],
+                checksum
             )));
         }
         let command_type = CommandType::try_from(data[3])?;
 
         // Some responses have a response code, some don't.
         let response_code = if command_has_response_code(command_type, len - 2) {
+            if len - 2 < 4 {
+                return Err(Error::Program(format!(
+                    "Missing response code for {:?}",
+                    command_type
+                )));
+            }
             Some(ResponseCode::try_from(data[4])?)
         } else {
-            None   
+            None
         };
 
         let data_offset = match response_code {
             Some(_) => 5,
-            None => 4
+            None => 4,
         };
 
         Response {
@@ -267,6 +712,7 @@ impl Response {
         match self.status {
             Some(ResponseCode::Success) => Ok(self),
             Some(ResponseCode::NoTagError) => Ok(self),
+            Some(ResponseCode::BufferEmptyError) => Ok(self),
             None => Ok(self),
             Some(status) => Err(Error::from(status)),
         }
@@ -284,8 +730,14 @@ pub struct InventoryItem {
     pub pc: Vec<u8>,
     /// EPC (Tag ID)
     pub epc: Vec<u8>,
+    /// TID, if returned by the tag using Impinj FastTID
+    pub tid: Option<Vec<u8>>,
     /// Relative Signal Strength Indicator (dBm, notionally)
     pub rssi: i8,
+    /// Number of times the tag was read (always 1 for real-time inventory)
+    pub read_count: u8,
+    /// RF phase angle, if requested
+    pub phase: Option<u16>,
 }
 
 impl InventoryItem {
@@ -298,7 +750,177 @@ impl InventoryItem {
             antenna: reader.read_u8(2)?,
             pc: data[1..3].to_owned(),
             epc: data[3..len - 1].to_owned(),
+            tid: None,
             rssi: convert_rssi(data[len - 1]),
+            read_count: 1,
+            phase: None,
+        })
+    }
+
+    /// Parse a real-time inventory tag packet which has the phase appended
+    pub(crate) fn from_bytes_with_phase(data: &[u8]) -> Result<InventoryItem> {
+        let len = data.len();
+        let mut item = InventoryItem::from_bytes(&data[..len - 2])?;
+        item.phase = Some(u16::from_be_bytes([data[len - 2], data[len - 1]]));
+        Ok(item)
+    }
+
+    /// Parse a tag record from the inventory buffer
+    ///
+    /// Returns a tuple of (number of tags in the buffer, item).
+    pub(crate) fn from_buffer_bytes(packet: &[u8]) -> Result<(usize, InventoryItem)> {
+        let mut reader = BitReader::new(packet);
+        let tag_count = reader.read_u16(16)?;
+        let data_len = reader.read_u8(8)? as usize;
+        // The data here is 2 bytes PC, (data_len - 4) bytes EPC, 2 bytes checksum.
+        if data_len < 4 {
+            return Err(Error::Program(format!(
+                "Short inventory buffer response: {:?}",
+                packet
+            )));
+        }
+        let mut data = Vec::new();
+        for _i in 0..data_len {
+            data.push(reader.read_u8(8)?);
+        }
+
+        let rssi = convert_rssi(reader.read_u8(8)?);
+        let frequency = convert_to_frequency(reader.read_u8(6)?);
+        let antenna = reader.read_u8(2)?;
+        let read_count = reader.read_u8(8)?;
+
+        Ok((
+            tag_count as usize,
+            InventoryItem {
+                frequency,
+                antenna,
+                pc: data[0..2].to_vec(),
+                epc: data[2..(data_len - 2)].to_vec(),
+                tid: None,
+                rssi,
+                read_count,
+                phase: None,
+            },
+        ))
+    }
+
+    /// Split the TID from the EPC returned by tags with Impinj FastTID enabled
+    ///
+    /// These tags append their TID to the EPC, but the EPC length in the PC word still only
+    /// covers the EPC itself, so anything beyond that length is the TID. Only call this when
+    /// FastTID is enabled, as XPC tags can also return more than the PC word's EPC length.
+    pub(crate) fn split_fast_tid(&mut self) {
+        let epc_len = (self.pc[0] >> 3) as usize * 2;
+        if self.tid.is_none() && epc_len > 0 && self.epc.len() > epc_len {
+            self.tid = Some(self.epc.split_off(epc_len));
+        }
+    }
+}
+
+/// Tags read on one antenna during a fast antenna-switching inventory
+#[derive(PartialEq, Debug)]
+pub struct AntennaInventory {
+    /// Antenna ID
+    pub antenna: u8,
+    /// List of tags scanned on this antenna
+    pub items: Vec<InventoryItem>,
+    /// Error reported for this antenna, such as `ResponseCode::AntennaMissingError`
+    pub error: Option<ResponseCode>,
+}
+
+/// The result of a fast antenna-switching inventory operation
+#[derive(PartialEq, Debug)]
+pub struct FastSwitchInventoryResult {
+    /// Results for each antenna, in the order they were first scanned
+    pub antennas: Vec<AntennaInventory>,
+    /// Total number of tags read
+    pub total_read: u32,
+    /// Time taken by the reader to run the inventory
+    pub duration: Duration,
+}
+
+impl FastSwitchInventoryResult {
+    /// Create an empty result listing the antennas in `sequence`
+    pub(crate) fn new(sequence: &[(u8, u8)]) -> FastSwitchInventoryResult {
+        let mut result = FastSwitchInventoryResult {
+            antennas: Vec::new(),
+            total_read: 0,
+            duration: Duration::from_millis(0),
+        };
+        for (antenna, _) in sequence {
+            result.antenna(*antenna);
+        }
+        result
+    }
+
+    /// Get the results for an antenna, adding it if it hasn't been seen yet
+    pub(crate) fn antenna(&mut self, antenna: u8) -> &mut AntennaInventory {
+        let idx = match self.antennas.iter().position(|a| a.antenna == antenna) {
+            Some(idx) => idx,
+            None => {
+                self.antennas.push(AntennaInventory {
+                    antenna,
+                    items: Vec::new(),
+                    error: None,
+                });
+                self.antennas.len() - 1
+            }
+        };
+        &mut self.antennas[idx]
+    }
+
+    /// Add a packet from the reader to the result.
+    ///
+    /// Returns true if this was the final packet of the inventory.
+    pub(crate) fn add_packet(&mut self, data: &[u8]) -> Result<bool> {
+        // Tag packets always have an even length as EPCs are a whole number of words, so
+        // they can't be confused with the antenna error or summary packets.
+        match data.len() {
+            2 => {
+                self.antenna(data[0]).error = Some(ResponseCode::try_from(data[1])?);
+                Ok(false)
+            }
+            7 => {
+                let mut reader = BitReader::new(data);
+                self.total_read = reader.read_u32(24)?;
+                self.duration = Duration::from_millis(u64::from(reader.read_u32(32)?));
+                Ok(true)
+            }
+            // Antenna and frequency, PC, EPC and RSSI
+            len if len >= 4 && len.is_multiple_of(2) => {
+                let item = InventoryItem::from_bytes(data)?;
+                self.antenna(item.antenna).items.push(item);
+                Ok(false)
+            }
+            _ => Err(Error::Program(format!(
+                "Unexpected fast switch inventory packet: {:?}",
+                data
+            ))),
+        }
+    }
+}
+
+/// The result of an inventory operation into the reader's buffer
+#[derive(PartialEq, Debug)]
+pub struct BufferedInventoryResult {
+    /// Antenna used
+    pub antenna: u8,
+    /// Number of unique tags in the buffer
+    pub tag_count: u16,
+    /// Read rate (tags/second)
+    pub read_rate: u16,
+    /// Total number of tags read
+    pub total_read: u32,
+}
+
+impl BufferedInventoryResult {
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<BufferedInventoryResult> {
+        let mut reader = BitReader::new(data);
+        Ok(BufferedInventoryResult {
+            antenna: reader.read_u8(8)?,
+            tag_count: reader.read_u16(16)?,
+            read_rate: reader.read_u16(16)?,
+            total_read: reader.read_u32(32)?,
         })
     }
 }
@@ -328,6 +950,38 @@ impl InventoryResult {
     }
 }
 
+/// Connection state of an antenna port
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AntennaStatus {
+    Connected,
+    Disconnected,
+    /// An antenna appears to be connected, but its return loss is poor
+    PoorMatch,
+}
+
+/// The result of an antenna health check for one antenna port
+#[derive(PartialEq, Debug)]
+pub struct AntennaHealth {
+    /// Antenna ID
+    pub antenna: u8,
+    pub status: AntennaStatus,
+    /// Return loss in dB
+    pub return_loss: i8,
+    /// Antenna connection detector threshold in dB, or 0 if disabled
+    pub detector_threshold: i8,
+}
+
+/// Events reported during a GPIO-triggered inventory
+#[derive(PartialEq, Debug)]
+pub enum TriggerEvent {
+    /// The trigger input became active
+    Start,
+    /// The result of one inventory run while the trigger was active
+    Inventory(InventoryResult),
+    /// The trigger input became inactive
+    Stop,
+}
+
 /// The result of a successful read operation
 #[derive(PartialEq, Debug)]
 pub struct ReadResult {
@@ -369,6 +1023,196 @@ impl ReadResult {
     }
 }
 
+/// Fields common to the per-tag replies of the write, lock and kill commands
+struct AccessResponse {
+    tag_count: usize,
+    epc: Vec<u8>,
+    status: ResponseCode,
+    frequency: f32,
+    antenna: u8,
+    count: u8,
+}
+
+impl AccessResponse {
+    fn from_bytes(packet: &[u8]) -> Result<AccessResponse> {
+        let mut reader = BitReader::new(packet);
+        let tag_count = reader.read_u16(16)?;
+        let data_len = reader.read_u8(8)? as usize;
+        // The data here is 2 bytes PC, (data_len - 4) bytes EPC, 2 bytes checksum.
+        if data_len < 4 {
+            return Err(Error::Program(format!(
+                "Short tag access response: {:?}",
+                packet
+            )));
+        }
+        let mut data = Vec::new();
+        for _i in 0..data_len {
+            data.push(reader.read_u8(8)?);
+        }
+
+        let status = ResponseCode::try_from(reader.read_u8(8)?)?;
+        let frequency = convert_to_frequency(reader.read_u8(6)?);
+        let antenna = reader.read_u8(2)?;
+        let count = reader.read_u8(8)?;
+
+        Ok(AccessResponse {
+            tag_count: tag_count as usize,
+            epc: data[2..(data_len - 2)].to_vec(),
+            status,
+            frequency,
+            antenna,
+            count,
+        })
+    }
+}
+
+/// Build the data for a write command
+pub(crate) fn write_data(
+    bank: MemoryBank,
+    password: &[u8],
+    start_word: u8,
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    if data.is_empty() || !data.len().is_multiple_of(2) || data.len() / 2 > 255 {
+        return Err(Error::Program(format!(
+            "Write data must be between 1 and 255 words, got {} bytes",
+            data.len()
+        )));
+    }
+    let mut cmd_data = password.to_vec();
+    cmd_data.extend(&[bank as u8, start_word, (data.len() / 2) as u8]);
+    cmd_data.extend(data);
+    Ok(cmd_data)
+}
+
+/// The result of a write operation on a single tag
+#[derive(PartialEq, Debug)]
+pub struct WriteResult {
+    pub epc: Vec<u8>,
+    /// Outcome of the write on this tag - `ResponseCode::Success` or a tag error code.
+    pub status: ResponseCode,
+    pub frequency: f32,
+    pub antenna: u8,
+    pub write_count: u8,
+}
+
+impl WriteResult {
+    pub(crate) fn from_bytes(packet: &[u8]) -> Result<(usize, WriteResult)> {
+        let res = AccessResponse::from_bytes(packet)?;
+        Ok((
+            res.tag_count,
+            WriteResult {
+                epc: res.epc,
+                status: res.status,
+                frequency: res.frequency,
+                antenna: res.antenna,
+                write_count: res.count,
+            },
+        ))
+    }
+}
+
+/// Memory regions which can be locked
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum LockTarget {
+    User = 0x01,
+    TID = 0x02,
+    EPC = 0x03,
+    AccessPassword = 0x04,
+    KillPassword = 0x05,
+}
+
+/// Lock operations which can be applied to a `LockTarget`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum LockAction {
+    /// Writable (or readable, for passwords) without the access password
+    Open = 0x00,
+    /// Only writable (or readable, for passwords) with the access password
+    Lock = 0x01,
+    /// Permanently writable - this cannot be undone
+    PermaUnlock = 0x02,
+    /// Permanently locked - this cannot be undone
+    PermaLock = 0x03,
+}
+
+/// The result of a lock operation on a single tag
+#[derive(PartialEq, Debug)]
+pub struct LockResult {
+    pub epc: Vec<u8>,
+    /// Outcome of the lock on this tag - `ResponseCode::Success` or a tag error code.
+    pub status: ResponseCode,
+    pub frequency: f32,
+    pub antenna: u8,
+    pub lock_count: u8,
+}
+
+impl LockResult {
+    pub(crate) fn from_bytes(packet: &[u8]) -> Result<(usize, LockResult)> {
+        let res = AccessResponse::from_bytes(packet)?;
+        Ok((
+            res.tag_count,
+            LockResult {
+                epc: res.epc,
+                status: res.status,
+                frequency: res.frequency,
+                antenna: res.antenna,
+                lock_count: res.count,
+            },
+        ))
+    }
+}
+
+/// A tag kill password
+///
+/// Gen2 tags can't be killed with a zero kill password, so this type can only hold a non-zero
+/// value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KillPassword([u8; 4]);
+
+impl KillPassword {
+    /// Create a kill password, returning `None` if the password is zero.
+    pub fn new(password: u32) -> Option<KillPassword> {
+        if password == 0 {
+            None
+        } else {
+            Some(KillPassword(password.to_be_bytes()))
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// The result of a kill operation on a single tag
+#[derive(PartialEq, Debug)]
+pub struct KillResult {
+    pub epc: Vec<u8>,
+    /// Outcome of the kill on this tag - `ResponseCode::Success` or a tag error code.
+    pub status: ResponseCode,
+    pub frequency: f32,
+    pub antenna: u8,
+    pub kill_count: u8,
+}
+
+impl KillResult {
+    pub(crate) fn from_bytes(packet: &[u8]) -> Result<(usize, KillResult)> {
+        let res = AccessResponse::from_bytes(packet)?;
+        Ok((
+            res.tag_count,
+            KillResult {
+                epc: res.epc,
+                status: res.status,
+                frequency: res.frequency,
+                antenna: res.antenna,
+                kill_count: res.count,
+            },
+        ))
+    }
+}
+
 #[test]
 fn test_checksum() {
     // Test vectors generated using example C code from datasheet
@@ -408,3 +1252,290 @@ fn test_read() {
     let result = ReadResult::from_bytes(&res.data);
     println!("{:?}", result);
 }
+
+#[test]
+fn test_write() {
+    let data = [
+        160, 25, 1, 130, 0, 1, 16, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 184,
+        151, 16, 21, 1, 162,
+    ];
+    let res = Response::from_bytes(&data).unwrap();
+    let (tag_count, result) = WriteResult::from_bytes(&res.data).unwrap();
+    assert_eq!(tag_count, 1);
+    assert_eq!(
+        result.epc,
+        vec![226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141]
+    );
+    assert_eq!(result.status, ResponseCode::Success);
+    assert_eq!(result.frequency, 867.5);
+    assert_eq!(result.antenna, 1);
+    assert_eq!(result.write_count, 1);
+
+    // A reply too short to hold the PC and CRC must not panic.
+    assert!(WriteResult::from_bytes(&[0, 1, 2, 48, 0, 16, 21, 1]).is_err());
+}
+
+#[test]
+fn test_kill_password() {
+    assert_eq!(KillPassword::new(0), None);
+    assert_eq!(
+        KillPassword::new(0x12345678).unwrap().as_bytes(),
+        &[0x12, 0x34, 0x56, 0x78]
+    );
+}
+
+#[test]
+fn test_lock_error() {
+    let data = [
+        160, 25, 1, 131, 0, 1, 16, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 184,
+        151, 52, 21, 1, 0,
+    ];
+    let data = [&data[..26], &[calculate_checksum(&data[..26])]].concat();
+    let res = Response::from_bytes(&data).unwrap();
+    let (tag_count, result) = LockResult::from_bytes(&res.data).unwrap();
+    assert_eq!(tag_count, 1);
+    assert_eq!(result.status, ResponseCode::TagLockError);
+    assert_eq!(result.lock_count, 1);
+}
+
+#[test]
+fn test_get_epc_match() {
+    let data = [160, 8, 1, 134, 0, 3, 1, 2, 3, 0];
+    let data = [&data[..9], &[calculate_checksum(&data[..9])]].concat();
+    let res = Response::from_bytes(&data).unwrap();
+    assert_eq!(res.status, None);
+    assert_eq!(res.data, vec![0, 3, 1, 2, 3]);
+
+    let data = [160, 4, 1, 134, 1, 0];
+    let data = [&data[..5], &[calculate_checksum(&data[..5])]].concat();
+    let res = Response::from_bytes(&data).unwrap();
+    assert_eq!(res.status, None);
+    assert_eq!(res.data, vec![1]);
+    assert_eq!(parse_epc_match(&res.data).unwrap(), None);
+}
+
+#[test]
+fn test_parse_epc_match() {
+    assert_eq!(
+        parse_epc_match(&[0, 3, 1, 2, 3]).unwrap(),
+        Some(vec![1, 2, 3])
+    );
+    assert!(parse_epc_match(&[0x00]).is_err());
+    assert!(parse_epc_match(&[0, 3, 1]).is_err());
+    assert!(parse_epc_match(&[]).is_err());
+}
+
+#[test]
+fn test_buffer_tag_count() {
+    assert_eq!(parse_buffer_tag_count(&[0x01, 0x02]).unwrap(), 258);
+    match parse_buffer_tag_count(&[0x11]) {
+        Err(Error::Protocol(ResponseCode::Fail)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_gpio_level() {
+    assert!(!parse_gpio_level(&[0x00, 0x01], GpioInput::Gpio1).unwrap());
+    assert!(parse_gpio_level(&[0x00, 0x01], GpioInput::Gpio2).unwrap());
+    match parse_gpio_level(&[0x11], GpioInput::Gpio1) {
+        Err(Error::Protocol(ResponseCode::Fail)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_inventory_buffer() {
+    let data = [
+        160, 25, 1, 144, 0, 2, 16, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 184,
+        151, 100, 21, 7, 0,
+    ];
+    let data = [&data[..26], &[calculate_checksum(&data[..26])]].concat();
+    let res = Response::from_bytes(&data).unwrap();
+    let (tag_count, item) = InventoryItem::from_buffer_bytes(&res.data).unwrap();
+    assert_eq!(tag_count, 2);
+    assert_eq!(item.pc, vec![48, 0]);
+    assert_eq!(
+        item.epc,
+        vec![226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141]
+    );
+    assert_eq!(item.rssi, -29);
+    assert_eq!(item.frequency, 867.5);
+    assert_eq!(item.antenna, 1);
+    assert_eq!(item.read_count, 7);
+
+    // A record too short to hold the PC and CRC must not panic.
+    assert!(InventoryItem::from_buffer_bytes(&[0, 1, 2, 48, 0, 100, 21, 7]).is_err());
+
+    // An empty buffer is reported with an error code rather than failing
+    let data = [160, 4, 1, 144, 0x38, 0];
+    let data = [&data[..5], &[calculate_checksum(&data[..5])]].concat();
+    let res = Response::from_bytes(&data).unwrap();
+    assert_eq!(res.status, Some(ResponseCode::BufferEmptyError));
+}
+
+#[test]
+fn test_fast_switch_inventory() {
+    let mut result = FastSwitchInventoryResult {
+        antennas: vec![],
+        total_read: 0,
+        duration: Duration::from_millis(0),
+    };
+    assert!(!result.add_packet(&[1, 0x22]).unwrap());
+    assert!(!result
+        .add_packet(&[0x14, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 100])
+        .unwrap());
+    assert!(result.add_packet(&[0, 0, 3, 0, 0, 1, 244]).unwrap());
+
+    assert_eq!(result.total_read, 3);
+    assert_eq!(result.duration, Duration::from_millis(500));
+    assert_eq!(result.antennas.len(), 2);
+    assert_eq!(result.antennas[0].antenna, 1);
+    assert_eq!(
+        result.antennas[0].error,
+        Some(ResponseCode::AntennaMissingError)
+    );
+    assert_eq!(result.antennas[1].antenna, 0);
+    assert_eq!(result.antennas[1].items.len(), 1);
+    assert_eq!(result.antennas[1].items[0].frequency, 867.5);
+
+    for packet in [&[][..], &[0x14], &[0x14, 48, 0]] {
+        assert!(result.add_packet(packet).is_err());
+    }
+}
+
+#[test]
+fn test_inventory_item_with_phase() {
+    let item = InventoryItem::from_bytes_with_phase(&[
+        0x14, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 100, 0x12, 0x34,
+    ])
+    .unwrap();
+    assert_eq!(
+        item.epc,
+        vec![226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141]
+    );
+    assert_eq!(item.rssi, -29);
+    assert_eq!(item.phase, Some(0x1234));
+}
+
+#[test]
+fn test_inventory_item_fast_tid() {
+    let item = InventoryItem::from_bytes(&[
+        0x14, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 100,
+    ])
+    .unwrap();
+    assert_eq!(
+        item.epc,
+        vec![226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141]
+    );
+    assert_eq!(item.tid, None);
+
+    let data = [
+        0x14, 48, 0, 226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141, 0xE2, 0x80, 0x11, 0x05, 0x20,
+        0x00, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 100,
+    ];
+    let mut item = InventoryItem::from_bytes(&data).unwrap();
+    assert_eq!(item.epc.len(), 24);
+    assert_eq!(item.tid, None);
+
+    item.split_fast_tid();
+    assert_eq!(
+        item.epc,
+        vec![226, 128, 104, 144, 32, 0, 80, 1, 8, 11, 1, 141]
+    );
+    assert_eq!(
+        item.tid,
+        Some(vec![
+            0xE2, 0x80, 0x11, 0x05, 0x20, 0x00, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC
+        ])
+    );
+    assert_eq!(item.rssi, -29);
+}
+
+#[test]
+fn test_region_config() {
+    let config = RegionConfig::Standard {
+        region: FrequencyRegion::ETSI,
+        start_channel: 0,
+        end_channel: 6,
+    };
+    assert!(config.validate().is_ok());
+    assert_eq!(config.to_bytes(), vec![0x02, 0, 6]);
+    assert_eq!(
+        RegionConfig::from_bytes(&config.to_bytes()).unwrap(),
+        config
+    );
+
+    let config = RegionConfig::UserDefined {
+        start_frequency: 915_250,
+        channel_spacing: 500,
+        channel_count: 4,
+    };
+    assert!(config.validate().is_ok());
+    assert_eq!(config.to_bytes(), vec![0x04, 50, 4, 0x0D, 0xF7, 0x32]);
+    assert_eq!(
+        RegionConfig::from_bytes(&config.to_bytes()).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn test_region_config_validation() {
+    let config = RegionConfig::Standard {
+        region: FrequencyRegion::FCC,
+        start_channel: 0,
+        end_channel: 59,
+    };
+    match config.validate() {
+        Err(Error::Protocol(ResponseCode::InvalidFrequencyRangeError)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let config = RegionConfig::Standard {
+        region: FrequencyRegion::UserDefined,
+        start_channel: 0,
+        end_channel: 1,
+    };
+    match config.validate() {
+        Err(Error::Protocol(ResponseCode::InvalidFrequencyRegionError)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let config = RegionConfig::UserDefined {
+        start_frequency: 959_000,
+        channel_spacing: 500,
+        channel_count: 4,
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_reader_identifier() {
+    let id = ReaderIdentifier::from_bytes(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 0xFF]).unwrap();
+    assert_eq!(id.to_string(), "000102030405060708090AFF");
+    assert!(ReaderIdentifier::from_bytes(&[0, 1, 2]).is_err());
+}
+
+#[test]
+fn test_link_profile() {
+    assert_eq!(
+        LinkProfile::from_response(&[0xD2]).unwrap(),
+        LinkProfile::Profile2
+    );
+    match LinkProfile::from_response(&[0x11]) {
+        Err(Error::Protocol(ResponseCode::Fail)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert!(LinkProfile::from_response(&[0x00]).is_err());
+    assert!(LinkProfile::from_response(&[]).is_err());
+}
+
+#[test]
+fn test_temperature() {
+    assert_eq!(parse_temperature(&[0x01, 25]).unwrap(), 25);
+    assert_eq!(parse_temperature(&[0x00, 5]).unwrap(), -5);
+    match parse_temperature(&[0x11]) {
+        Err(Error::Protocol(ResponseCode::Fail)) => (),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,226 @@
+//! Transports which carry the reader protocol
+//!
+//! `Reader` talks to the reader through the `Transport` trait, and `AsyncReader` through the
+//! `AsyncTransport` trait, so they can be used over links other than a local serial port.
+use log::warn;
+use serial::core::prelude::*;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::protocol::BaudRate;
+
+/// A bidirectional byte stream to a reader
+///
+/// Reads should block until at least one byte is available, failing with an `io::Error` once the
+/// timeout set by `set_timeout` has elapsed.
+pub trait Transport: io::Read + io::Write {
+    /// Set the timeout for reads
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()>;
+
+    /// Reconfigure the link to use a new baud rate
+    ///
+    /// This is called after the reader has been told to change its baud rate.
+    fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<()>;
+}
+
+impl Transport for serial::SystemPort {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        SerialPort::set_timeout(self, timeout)
+            .map_err(|e| format!("Failed to set serial port timeout: {}", e))?;
+        Ok(())
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<()> {
+        let baud_rate = match baud_rate {
+            BaudRate::Baud38400 => serial::Baud38400,
+            BaudRate::Baud115200 => serial::Baud115200,
+        };
+        self.reconfigure(&|settings| {
+            settings.set_baud_rate(baud_rate)?;
+            settings.set_char_size(serial::Bits8);
+            settings.set_parity(serial::ParityNone);
+            settings.set_stop_bits(serial::Stop1);
+            settings.set_flow_control(serial::FlowNone);
+            Ok(())
+        })
+        .map_err(|e| format!("Failed to configure serial port: {}", e))?;
+        Ok(())
+    }
+}
+
+/// A TCP connection to a reader with an Ethernet to serial bridge
+///
+/// If the connection drops, it is re-established on the next read or write. A write which fails
+/// is retried once on a new connection.
+pub struct TcpTransport {
+    addrs: Vec<SocketAddr>,
+    stream: Option<TcpStream>,
+    connect_timeout: Duration,
+    read_timeout: Option<Duration>,
+}
+
+impl TcpTransport {
+    /// Connect to a reader, waiting up to `connect_timeout` for the connection to succeed
+    pub fn connect<A: ToSocketAddrs>(addr: A, connect_timeout: Duration) -> Result<TcpTransport> {
+        let mut transport = TcpTransport {
+            addrs: addr.to_socket_addrs()?.collect(),
+            stream: None,
+            connect_timeout,
+            read_timeout: None,
+        };
+        transport.stream()?;
+        Ok(transport)
+    }
+
+    /// Get the current connection, connecting if necessary
+    fn stream(&mut self) -> io::Result<&mut TcpStream> {
+        if self.stream.is_none() {
+            self.stream = Some(self.open()?);
+        }
+        Ok(self.stream.as_mut().unwrap())
+    }
+
+    fn open(&self) -> io::Result<TcpStream> {
+        let mut error = io::Error::new(io::ErrorKind::InvalidInput, "No address to connect to");
+        for addr in &self.addrs {
+            match TcpStream::connect_timeout(addr, self.connect_timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(self.read_timeout)?;
+                    stream.set_nodelay(true)?;
+                    return Ok(stream);
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.stream()?.read(buf);
+        match result {
+            Ok(0) if !buf.is_empty() => {
+                self.stream = None;
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Connection closed by reader",
+                ))
+            }
+            Err(ref e)
+                if e.kind() != io::ErrorKind::WouldBlock
+                    && e.kind() != io::ErrorKind::TimedOut
+                    && e.kind() != io::ErrorKind::Interrupted =>
+            {
+                self.stream = None;
+                result
+            }
+            _ => result,
+        }
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.stream()?.write(buf) {
+            Ok(len) => Ok(len),
+            Err(e) => {
+                warn!("Write to reader failed, reconnecting: {}", e);
+                self.stream = None;
+                self.stream()?.write(buf)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream()?.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.read_timeout = Some(timeout);
+        if let Some(ref stream) = self.stream {
+            stream.set_read_timeout(self.read_timeout)?;
+        }
+        Ok(())
+    }
+
+    /// The baud rate of the bridge's serial port can't be changed over the network.
+    fn set_baud_rate(&mut self, _baud_rate: BaudRate) -> Result<()> {
+        Err(Error::Unsupported(
+            "Changing baud rate over TCP/IP".to_string(),
+        ))
+    }
+}
+
+/// A bidirectional asynchronous byte stream to a reader, for use with `AsyncReader`
+///
+/// Reads may wait indefinitely, as `AsyncReader` applies its own timeout to each reply.
+#[cfg(feature = "async")]
+pub trait AsyncTransport: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin {
+    /// Set the timeout for reads, for transports which have their own
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()>;
+
+    /// Reconfigure the link to use a new baud rate
+    ///
+    /// This is called after the reader has been told to change its baud rate.
+    fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<()>;
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for tokio_serial::SerialStream {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        tokio_serial::SerialPort::set_timeout(self, timeout)
+            .map_err(|e| format!("Failed to set serial port timeout: {}", e))?;
+        Ok(())
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: BaudRate) -> Result<()> {
+        let baud_rate = match baud_rate {
+            BaudRate::Baud38400 => 38_400,
+            BaudRate::Baud115200 => 115_200,
+        };
+        tokio_serial::SerialPort::set_baud_rate(self, baud_rate)
+            .map_err(|e| format!("Failed to configure serial port: {}", e))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for tokio::net::TcpStream {
+    /// TCP streams have no read timeout of their own.
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<()> {
+        Ok(())
+    }
+
+    /// The baud rate of the bridge's serial port can't be changed over the network.
+    fn set_baud_rate(&mut self, _baud_rate: BaudRate) -> Result<()> {
+        Err(Error::Unsupported(
+            "Changing baud rate over TCP/IP".to_string(),
+        ))
+    }
+}
+
+#[test]
+fn test_tcp_reconnect() {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut transport =
+        TcpTransport::connect(listener.local_addr().unwrap(), Duration::from_secs(1)).unwrap();
+    transport.set_timeout(Duration::from_secs(1)).unwrap();
+
+    // Close the first connection, and check the next read reports it and the one after
+    // reconnects.
+    drop(listener.accept().unwrap());
+    let mut buf = [0u8; 1];
+    assert!(transport.read(&mut buf).is_err());
+    transport.write_all(&[0xA0]).unwrap();
+    let (mut conn, _) = listener.accept().unwrap();
+    conn.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, [0xA0]);
+}